@@ -1,8 +1,8 @@
 use std::{path::PathBuf, sync::Arc};
 
 use shared::{
-    chapter_storage::ChapterStorage, model::ChapterId, source_collection::SourceCollection,
-    source_manager::SourceManager, usecases,
+    chapter_downloader::ChapterDownloader, chapter_storage::ChapterStorage, model::ChapterId,
+    source_collection::SourceCollection, source_manager::SourceManager, usecases,
 };
 use tokio::sync::Mutex;
 
@@ -17,6 +17,7 @@ impl DownloadChapterJob {
     pub fn spawn_new(
         source_manager: Arc<Mutex<SourceManager>>,
         chapter_storage: ChapterStorage,
+        chapter_downloader: ChapterDownloader,
         chapter_id: ChapterId,
         chapter_num: Option<f64>,
     ) -> Self {
@@ -24,8 +25,16 @@ impl DownloadChapterJob {
         let output_clone = output.clone();
 
         tokio::spawn(async move {
-            *output_clone.lock().await =
-                Some(Self::do_job(source_manager, chapter_storage, chapter_id, chapter_num).await);
+            *output_clone.lock().await = Some(
+                Self::do_job(
+                    source_manager,
+                    chapter_storage,
+                    chapter_downloader,
+                    chapter_id,
+                    chapter_num,
+                )
+                .await,
+            );
         });
 
         Self(output)
@@ -34,6 +43,7 @@ impl DownloadChapterJob {
     async fn do_job(
         source_manager: Arc<Mutex<SourceManager>>,
         chapter_storage: ChapterStorage,
+        chapter_downloader: ChapterDownloader,
         chapter_id: ChapterId,
         chapter_num: Option<f64>,
     ) -> Result<PathBuf, ErrorResponse> {
@@ -42,11 +52,15 @@ impl DownloadChapterJob {
             .get_by_id(chapter_id.source_id())
             .ok_or(AppError::SourceNotFound)?;
 
-        Ok(
-            usecases::fetch_manga_chapter(source, &chapter_storage, &chapter_id, chapter_num)
-                .await
-                .map_err(AppError::from)?,
+        Ok(usecases::fetch_manga_chapter(
+            source,
+            &chapter_storage,
+            &chapter_downloader,
+            &chapter_id,
+            chapter_num,
         )
+        .await
+        .map_err(AppError::from)?)
     }
 }
 
@@ -1,5 +1,6 @@
 use serde::Serialize;
 use shared::{
+    chapter_downloader::ChapterDownloader,
     chapter_storage::ChapterStorage,
     database::Database,
     model::MangaId,
@@ -38,6 +39,7 @@ impl DownloadScanlatorChaptersJob {
         source: Source,
         database: Arc<Database>,
         chapter_storage: ChapterStorage,
+        chapter_downloader: ChapterDownloader,
         manga_id: MangaId,
         scanlator_filter: ScanlatorFilter,
     ) -> Self {
@@ -63,6 +65,7 @@ impl DownloadScanlatorChaptersJob {
                     &source,
                     &database,
                     &chapter_storage,
+                    &chapter_downloader,
                     manga_id,
                     filter,
                 );
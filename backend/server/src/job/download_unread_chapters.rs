@@ -4,6 +4,7 @@ use anyhow::anyhow;
 use futures::{lock::Mutex, pin_mut, StreamExt};
 use serde::Serialize;
 use shared::{
+    chapter_downloader::ChapterDownloader,
     chapter_storage::ChapterStorage,
     database::Database,
     model::MangaId,
@@ -43,6 +44,7 @@ impl DownloadUnreadChaptersJob {
         source: Source,
         database: Arc<Database>,
         chapter_storage: ChapterStorage,
+        chapter_downloader: ChapterDownloader,
         manga_id: MangaId,
         filter: ChapterToDownloadFilter,
     ) -> Self {
@@ -61,6 +63,7 @@ impl DownloadUnreadChaptersJob {
                 &source,
                 &database,
                 &chapter_storage,
+                &chapter_downloader,
                 manga_id,
                 filter,
             );
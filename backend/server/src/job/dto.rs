@@ -5,7 +5,9 @@ use super::{
     download_chapter::DownloadChapterJob,
     download_scanlator_chapters::DownloadScanlatorChaptersJob,
     download_unread_chapters::DownloadUnreadChaptersJob,
+    migrate_chapter_storage::MigrateChapterStorageJob,
     state::{Job, JobState, RunningJob},
+    update_sources::UpdateSourcesJob,
 };
 
 #[derive(Serialize)]
@@ -26,6 +28,10 @@ impl JobDetail {
             RunningJob::DownloadScanlatorChapters(job) => {
                 Self::from_download_scanlator_chapters_job(job).await
             }
+            RunningJob::MigrateChapterStorage(job) => {
+                Self::from_migrate_chapter_storage_job(job).await
+            }
+            RunningJob::UpdateSources(job) => Self::from_update_sources_job(job).await,
         }
     }
 
@@ -67,4 +73,32 @@ impl JobDetail {
             JobState::Errored(v) => (JobDetail::Error(serde_json::to_value(v).unwrap()), None),
         }
     }
+
+    async fn from_migrate_chapter_storage_job(
+        job: MigrateChapterStorageJob,
+    ) -> (Self, Option<RunningJob>) {
+        match job.poll().await {
+            JobState::InProgress(v) => (
+                JobDetail::Pending(serde_json::to_value(v).unwrap()),
+                Some(RunningJob::MigrateChapterStorage(job)),
+            ),
+            JobState::Completed(v) => {
+                (JobDetail::Completed(serde_json::to_value(v).unwrap()), None)
+            }
+            JobState::Errored(v) => (JobDetail::Error(serde_json::to_value(v).unwrap()), None),
+        }
+    }
+
+    async fn from_update_sources_job(job: UpdateSourcesJob) -> (Self, Option<RunningJob>) {
+        match job.poll().await {
+            JobState::InProgress(v) => (
+                JobDetail::Pending(serde_json::to_value(v).unwrap()),
+                Some(RunningJob::UpdateSources(job)),
+            ),
+            JobState::Completed(v) => {
+                (JobDetail::Completed(serde_json::to_value(v).unwrap()), None)
+            }
+            JobState::Errored(v) => (JobDetail::Error(serde_json::to_value(v).unwrap()), None),
+        }
+    }
 }
@@ -0,0 +1,184 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::anyhow;
+use futures::{lock::Mutex, pin_mut, StreamExt};
+use log::error;
+use serde::Serialize;
+use shared::{
+    chapter_storage::ChapterStorage,
+    database::Database,
+    model::SourceId,
+    usecases::{
+        self,
+        migrate_chapter_storage::{ProgressReport, SkipReason, SkippedChapterFile},
+    },
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{model::LegacyChaptersMigrationReport, AppError, ErrorResponse};
+
+use super::state::{Job, JobState};
+
+#[derive(Default)]
+enum Status {
+    #[default]
+    Initializing,
+    Initialized(ProgressReport),
+    Finished(Output),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE", tag = "type")]
+pub enum Progress {
+    Initializing,
+    Migrating {
+        migrated: usize,
+        skipped: usize,
+        total: usize,
+    },
+}
+
+#[derive(Serialize, Clone)]
+pub struct Output {
+    migrated: usize,
+    skipped: Vec<SkippedFile>,
+    /// Missing if the legacy chapters couldn't be migrated.
+    legacy_chapters: Option<LegacyChaptersMigrationReport>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct SkippedFile {
+    path: String,
+    reason: SkippedFileReason,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SkippedFileReason {
+    UnrecognizedName,
+    AlreadyStored,
+    StorageFull,
+}
+
+impl From<&SkippedChapterFile> for SkippedFile {
+    fn from(value: &SkippedChapterFile) -> Self {
+        Self {
+            path: value.path.display().to_string(),
+            reason: match value.reason {
+                SkipReason::UnrecognizedName => SkippedFileReason::UnrecognizedName,
+                SkipReason::AlreadyStored => SkippedFileReason::AlreadyStored,
+                SkipReason::StorageFull => SkippedFileReason::StorageFull,
+            },
+        }
+    }
+}
+
+pub struct MigrateChapterStorageJob {
+    cancellation_token: CancellationToken,
+    status: Arc<Mutex<Status>>,
+}
+
+impl MigrateChapterStorageJob {
+    pub fn spawn_new(
+        db: Arc<Database>,
+        chapter_storage: Arc<tokio::sync::Mutex<ChapterStorage>>,
+        previous_downloads_folder_path: PathBuf,
+        source_ids: Vec<SourceId>,
+    ) -> Self {
+        let cancellation_token = CancellationToken::new();
+        let cancellation_token_clone = cancellation_token.clone();
+
+        let status: Arc<Mutex<Status>> = Default::default();
+        let status_clone = status.clone();
+
+        tokio::spawn(async move {
+            let status = status_clone;
+
+            let progress_report_stream = usecases::migrate_chapter_storage(
+                cancellation_token_clone,
+                chapter_storage.lock().await.clone(),
+                previous_downloads_folder_path,
+                source_ids,
+            );
+
+            pin_mut!(progress_report_stream);
+
+            while let Some(progress_report) = progress_report_stream.next().await {
+                let (migrated, skipped) = match progress_report {
+                    ProgressReport::Finished { migrated, skipped }
+                    | ProgressReport::Cancelled { migrated, skipped } => (migrated, skipped),
+                    progress_report => {
+                        *status.lock().await = Status::Initialized(progress_report);
+
+                        continue;
+                    }
+                };
+
+                // The moved chapters might be named with the legacy path format, which the
+                // chapter storage might have stopped looking for.
+                let legacy_chapters =
+                    usecases::migrate_legacy_chapter_paths(&db, &mut *chapter_storage.lock().await)
+                        .await
+                        .inspect_err(|e| error!("couldn't migrate legacy chapter paths: {e:?}"))
+                        .ok()
+                        .map(LegacyChaptersMigrationReport::from);
+
+                *status.lock().await = Status::Finished(Output {
+                    migrated,
+                    skipped: skipped.iter().map(SkippedFile::from).collect(),
+                    legacy_chapters,
+                });
+
+                break;
+            }
+        });
+
+        Self {
+            cancellation_token,
+            status,
+        }
+    }
+}
+
+impl Job for MigrateChapterStorageJob {
+    type Progress = Progress;
+    type Output = Output;
+    type Error = ErrorResponse;
+
+    async fn cancel(&self) -> Result<(), AppError> {
+        self.cancellation_token.cancel();
+
+        Ok(())
+    }
+
+    async fn poll(&self) -> JobState<Self::Progress, Self::Output, Self::Error> {
+        let status = &*self.status.lock().await;
+
+        match status {
+            Status::Initializing => JobState::InProgress(Progress::Initializing),
+            Status::Initialized(report) => match report {
+                ProgressReport::Progressing {
+                    migrated,
+                    skipped,
+                    total,
+                } => JobState::InProgress(Progress::Migrating {
+                    migrated: *migrated,
+                    skipped: *skipped,
+                    total: *total,
+                }),
+                // Never stored, as the output is only known once the legacy chapters are migrated.
+                ProgressReport::Finished { .. } | ProgressReport::Cancelled { .. } => {
+                    JobState::InProgress(Progress::Initializing)
+                }
+                ProgressReport::Errored(e) => {
+                    let error = AppError::from(anyhow!(e.to_string()));
+
+                    JobState::Errored(error.into())
+                }
+            },
+            // Chapters that were already moved stay in the new storage, so there's nothing to
+            // undo when cancelled, and they're reported just like when finished.
+            Status::Finished(output) => JobState::Completed(output.clone()),
+        }
+    }
+}
@@ -2,8 +2,10 @@ mod download_chapter;
 mod download_scanlator_chapters;
 mod download_unread_chapters;
 mod dto;
+mod migrate_chapter_storage;
 mod routes;
 mod state;
+mod update_sources;
 
 pub use routes::routes;
 pub use state::State;
@@ -9,7 +9,7 @@ use serde::Deserialize;
 use shared::{
     model::{ChapterId, MangaId},
     source_collection::SourceCollection,
-    usecases::fetch_manga_chapters_in_batch::Filter as ChaptersToDownloadFilter,
+    usecases::{self, fetch_manga_chapters_in_batch::Filter as ChaptersToDownloadFilter},
 };
 use uuid::Uuid;
 
@@ -21,7 +21,9 @@ use super::{
     download_chapter::DownloadChapterJob,
     download_scanlator_chapters::{DownloadScanlatorChaptersJob, ScanlatorFilter},
     download_unread_chapters::DownloadUnreadChaptersJob,
+    migrate_chapter_storage::MigrateChapterStorageJob,
     state::Job,
+    update_sources::UpdateSourcesJob,
 };
 
 pub fn routes() -> Router<AppState> {
@@ -35,6 +37,11 @@ pub fn routes() -> Router<AppState> {
             "/jobs/download-scanlator-chapters",
             post(create_download_scanlator_chapters_job),
         )
+        .route(
+            "/jobs/migrate-chapter-storage",
+            post(create_migrate_chapter_storage_job),
+        )
+        .route("/jobs/update-sources", post(create_update_sources_job))
         .route("/jobs/:id", get(get_job))
         .route("/jobs/:id", delete(cancel_job))
 }
@@ -57,6 +64,7 @@ async fn create_download_chapter_job(
     StateExtractor(AppState {
         source_manager,
         chapter_storage,
+        chapter_downloader,
         ..
     }): StateExtractor<AppState>,
     StateExtractor(State { job_registry }): StateExtractor<State>,
@@ -65,8 +73,14 @@ async fn create_download_chapter_job(
     let id = Uuid::new_v4();
     let chapter_num = body.chapter_num;
     let chapter_storage = chapter_storage.lock().await.clone();
-    let job =
-        DownloadChapterJob::spawn_new(source_manager, chapter_storage, body.into(), chapter_num);
+    let chapter_downloader = chapter_downloader.lock().await.clone();
+    let job = DownloadChapterJob::spawn_new(
+        source_manager,
+        chapter_storage,
+        chapter_downloader,
+        body.into(),
+        chapter_num,
+    );
 
     job_registry
         .lock()
@@ -94,6 +108,7 @@ async fn create_download_unread_chapters_job(
         source_manager,
         database,
         chapter_storage,
+        chapter_downloader,
         ..
     }): StateExtractor<AppState>,
     StateExtractor(State { job_registry }): StateExtractor<State>,
@@ -113,8 +128,15 @@ async fn create_download_unread_chapters_job(
 
     let id = Uuid::new_v4();
     let chapter_storage = chapter_storage.lock().await.clone();
-    let job =
-        DownloadUnreadChaptersJob::spawn_new(source, database, chapter_storage, manga_id, filter);
+    let chapter_downloader = chapter_downloader.lock().await.clone();
+    let job = DownloadUnreadChaptersJob::spawn_new(
+        source,
+        database,
+        chapter_storage,
+        chapter_downloader,
+        manga_id,
+        filter,
+    );
 
     job_registry
         .lock()
@@ -143,6 +165,7 @@ async fn create_download_scanlator_chapters_job(
         source_manager,
         database,
         chapter_storage,
+        chapter_downloader,
         ..
     }): StateExtractor<AppState>,
     StateExtractor(State { job_registry }): StateExtractor<State>,
@@ -163,10 +186,12 @@ async fn create_download_scanlator_chapters_job(
 
     let id = Uuid::new_v4();
     let chapter_storage = chapter_storage.lock().await.clone();
+    let chapter_downloader = chapter_downloader.lock().await.clone();
     let job = DownloadScanlatorChaptersJob::spawn_new(
         source,
         database,
         chapter_storage,
+        chapter_downloader,
         manga_id,
         scanlator_filter,
     );
@@ -179,6 +204,63 @@ async fn create_download_scanlator_chapters_job(
     Ok(Json(id))
 }
 
+async fn create_migrate_chapter_storage_job(
+    StateExtractor(AppState {
+        database,
+        chapter_storage,
+        source_manager,
+        ..
+    }): StateExtractor<AppState>,
+    StateExtractor(State { job_registry }): StateExtractor<State>,
+) -> Result<Json<Uuid>, AppError> {
+    let previous_downloads_folder_path = chapter_storage
+        .lock()
+        .await
+        .previous_downloads_folder_path()
+        .ok_or_else(|| anyhow!("the storage path wasn't changed, so there's nothing to migrate"))?
+        .to_owned();
+    let source_ids = usecases::list_installed_sources(&*source_manager.lock().await)
+        .into_iter()
+        .map(|installed_source| installed_source.information.id)
+        .collect();
+
+    let id = Uuid::new_v4();
+    let job = MigrateChapterStorageJob::spawn_new(
+        database,
+        chapter_storage,
+        previous_downloads_folder_path,
+        source_ids,
+    );
+
+    job_registry
+        .lock()
+        .await
+        .insert(id, RunningJob::MigrateChapterStorage(job));
+
+    Ok(Json(id))
+}
+
+async fn create_update_sources_job(
+    StateExtractor(AppState {
+        source_manager,
+        http_client,
+        settings,
+        ..
+    }): StateExtractor<AppState>,
+    StateExtractor(State { job_registry }): StateExtractor<State>,
+) -> Result<Json<Uuid>, AppError> {
+    let id = Uuid::new_v4();
+    let source_lists = settings.lock().await.source_lists.clone();
+    let job = UpdateSourcesJob::spawn_new(source_manager, http_client, source_lists);
+
+    job_registry
+        .lock()
+        .await
+        .insert(id, RunningJob::UpdateSources(job));
+
+    Ok(Json(id))
+}
+
 #[derive(Deserialize)]
 struct GetJobParams {
     id: Uuid,
@@ -215,6 +297,8 @@ async fn cancel_job(
     match job {
         RunningJob::DownloadUnreadChapters(job) => job.cancel().await?,
         RunningJob::DownloadScanlatorChapters(job) => job.cancel().await?,
+        RunningJob::MigrateChapterStorage(job) => job.cancel().await?,
+        RunningJob::UpdateSources(job) => job.cancel().await?,
         _ => Err(anyhow!("job is not cancellable"))?,
     };
 
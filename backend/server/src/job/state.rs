@@ -9,6 +9,7 @@ use super::{
     download_chapter::DownloadChapterJob,
     download_scanlator_chapters::DownloadScanlatorChaptersJob,
     download_unread_chapters::DownloadUnreadChaptersJob,
+    migrate_chapter_storage::MigrateChapterStorageJob, update_sources::UpdateSourcesJob,
 };
 
 pub enum JobState<Progress, Output, Error> {
@@ -30,6 +31,8 @@ pub enum RunningJob {
     DownloadChapter(DownloadChapterJob),
     DownloadUnreadChapters(DownloadUnreadChaptersJob),
     DownloadScanlatorChapters(DownloadScanlatorChaptersJob),
+    MigrateChapterStorage(MigrateChapterStorageJob),
+    UpdateSources(UpdateSourcesJob),
 }
 
 #[derive(Default, Clone)]
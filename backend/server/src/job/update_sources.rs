@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use futures::lock::Mutex;
+use reqwest::Url;
+use serde::Serialize;
+use shared::{source_manager::SourceManager, usecases};
+use tokio_util::sync::CancellationToken;
+
+use crate::{AppError, ErrorResponse};
+
+use super::state::{Job, JobState};
+
+#[derive(Default)]
+enum Status {
+    #[default]
+    Initializing,
+    Updating {
+        updated: usize,
+        total: usize,
+    },
+    Finished(Output),
+    Errored(String),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE", tag = "type")]
+pub enum Progress {
+    Initializing,
+    Updating { updated: usize, total: usize },
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct Output {
+    updated: Vec<String>,
+    failed: Vec<FailedUpdate>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct FailedUpdate {
+    source_id: String,
+    message: String,
+}
+
+pub struct UpdateSourcesJob {
+    cancellation_token: CancellationToken,
+    status: Arc<Mutex<Status>>,
+}
+
+impl UpdateSourcesJob {
+    pub fn spawn_new(
+        source_manager: Arc<tokio::sync::Mutex<SourceManager>>,
+        http_client: reqwest::Client,
+        source_lists: Vec<Url>,
+    ) -> Self {
+        let cancellation_token = CancellationToken::new();
+        let cancellation_token_clone = cancellation_token.clone();
+
+        let status: Arc<Mutex<Status>> = Default::default();
+        let status_clone = status.clone();
+
+        tokio::spawn(async move {
+            let status = status_clone;
+            let cancellation_token = cancellation_token_clone;
+
+            let installed_sources = usecases::list_installed_sources(&*source_manager.lock().await)
+                .into_iter()
+                .map(|installed_source| installed_source.information)
+                .collect();
+
+            let source_updates =
+                match usecases::list_source_updates(&http_client, source_lists, installed_sources)
+                    .await
+                {
+                    Ok(source_updates) => source_updates,
+                    Err(e) => {
+                        *status.lock().await = Status::Errored(format!("{e:?}"));
+
+                        return;
+                    }
+                };
+
+            let total = source_updates.len();
+            let mut output = Output::default();
+
+            for (index, source_update) in source_updates.into_iter().enumerate() {
+                if cancellation_token.is_cancelled() {
+                    break;
+                }
+
+                *status.lock().await = Status::Updating {
+                    updated: index,
+                    total,
+                };
+
+                let source_id = source_update.available.information.id.clone();
+
+                // Download the new version before locking the manager, so other requests aren't
+                // blocked while we wait on the network.
+                let result = match usecases::install_source::fetch_source_file(
+                    &http_client,
+                    &source_update.available,
+                )
+                .await
+                {
+                    Ok(contents) => source_manager
+                        .lock()
+                        .await
+                        .install_source(&source_id, contents),
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(_) => output.updated.push(source_id.value().clone()),
+                    Err(e) => output.failed.push(FailedUpdate {
+                        source_id: source_id.value().clone(),
+                        message: format!("{e:?}"),
+                    }),
+                }
+            }
+
+            *status.lock().await = Status::Finished(output);
+        });
+
+        Self {
+            cancellation_token,
+            status,
+        }
+    }
+}
+
+impl Job for UpdateSourcesJob {
+    type Progress = Progress;
+    type Output = Output;
+    type Error = ErrorResponse;
+
+    async fn cancel(&self) -> Result<(), AppError> {
+        self.cancellation_token.cancel();
+
+        Ok(())
+    }
+
+    async fn poll(&self) -> JobState<Self::Progress, Self::Output, Self::Error> {
+        let status = &*self.status.lock().await;
+
+        match status {
+            Status::Initializing => JobState::InProgress(Progress::Initializing),
+            Status::Updating { updated, total } => JobState::InProgress(Progress::Updating {
+                updated: *updated,
+                total: *total,
+            }),
+            // Sources that were already updated stay updated, so a cancelled job just reports
+            // what it managed to do.
+            Status::Finished(output) => JobState::Completed(output.clone()),
+            Status::Errored(e) => {
+                let error = AppError::from(anyhow!(e.clone()));
+
+                JobState::Errored(error.into())
+            }
+        }
+    }
+}
@@ -24,12 +24,15 @@ use axum::routing::get;
 use axum::{Json, Router};
 use clap::Parser;
 use serde::Serialize;
+use shared::chapter_downloader::ChapterDownloader;
 use shared::chapter_storage::ChapterStorage;
 use shared::database::Database;
+use shared::http::{self, RequestTimeoutError};
 use shared::settings::Settings;
+use shared::source::limits::ResourceExhaustedError;
 use shared::source_manager::SourceManager;
 use shared::usecases::{
-    fetch_manga_chapter::Error as FetchMangaChaptersError,
+    fetch_manga_chapter::Error as FetchMangaChaptersError, migrate_legacy_chapter_paths,
     search_mangas::Error as SearchMangasError,
 };
 use tokio::sync::Mutex;
@@ -81,13 +84,30 @@ async fn main() -> anyhow::Result<()> {
         .clone()
         .unwrap_or(default_downloads_folder_path);
 
-    let chapter_storage = ChapterStorage::new(downloads_folder_path, settings.storage_size_limit.0)
-        .context("couldn't initialize chapter storage")?;
+    let mut chapter_storage =
+        ChapterStorage::new(downloads_folder_path, settings.storage_size_limit.0)
+            .context("couldn't initialize chapter storage")?;
+
+    // Not being able to migrate is fine: the legacy paths will just keep being checked.
+    let _ = migrate_legacy_chapter_paths(&database, &mut chapter_storage)
+        .await
+        .inspect_err(|e| error!("couldn't migrate legacy chapter paths: {e:?}"));
+
+    let _ = chapter_storage
+        .remove_stale_partial_downloads()
+        .inspect_err(|e| error!("couldn't remove stale partial downloads: {e:?}"));
+
+    let chapter_downloader =
+        ChapterDownloader::new(&settings).context("couldn't create chapter downloader")?;
+    let http_client =
+        http::build_client(&settings.network).context("couldn't create HTTP client")?;
 
     let state = State {
         source_manager: Arc::new(Mutex::new(source_manager)),
         database: Arc::new(database),
         chapter_storage: Arc::new(Mutex::new(chapter_storage)),
+        chapter_downloader: Arc::new(Mutex::new(chapter_downloader)),
+        http_client,
         settings: Arc::new(Mutex::new(settings)),
         settings_path,
         job_state: Default::default(),
@@ -141,6 +161,8 @@ pub enum AppError {
     SourceNotFound,
     DownloadAllChaptersProgressNotFound,
     NetworkFailure(anyhow::Error),
+    Timeout(anyhow::Error),
+    ResourceExhausted(anyhow::Error),
     Other(anyhow::Error),
 }
 
@@ -152,12 +174,20 @@ pub struct ErrorResponse {
 impl AppError {
     fn from_search_mangas_error(value: SearchMangasError) -> Self {
         match value {
+            SearchMangasError::SourceError(e) if is_timeout(&e) => Self::Timeout(e),
+            SearchMangasError::SourceError(e) if exhausted_resource(&e).is_some() => {
+                Self::ResourceExhausted(e)
+            }
             SearchMangasError::SourceError(e) => Self::NetworkFailure(e),
         }
     }
 
     fn from_fetch_manga_chapters_error(value: FetchMangaChaptersError) -> Self {
         match value {
+            FetchMangaChaptersError::DownloadError(e) if is_timeout(&e) => Self::Timeout(e),
+            FetchMangaChaptersError::DownloadError(e) if exhausted_resource(&e).is_some() => {
+                Self::ResourceExhausted(e)
+            }
             FetchMangaChaptersError::DownloadError(e) => Self::NetworkFailure(e),
             FetchMangaChaptersError::Other(e) => Self::Other(e),
         }
@@ -170,6 +200,7 @@ impl From<&AppError> for StatusCode {
             AppError::SourceNotFound | AppError::DownloadAllChaptersProgressNotFound => {
                 StatusCode::NOT_FOUND
             }
+            AppError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -185,6 +216,16 @@ impl From<&AppError> for ErrorResponse {
             AppError::NetworkFailure(_) => {
                 "There was a network error. Check your connection and try again.".to_string()
             }
+            AppError::Timeout(_) => {
+                "The source took too long to respond. Try again later.".to_string()
+            }
+            AppError::ResourceExhausted(ref e) => match exhausted_resource(e) {
+                Some(exhausted) => format!(
+                    "The source was stopped for using too much {}.",
+                    exhausted.resource
+                ),
+                None => format!("Something went wrong: {}", e),
+            },
             AppError::Other(ref e) => format!("Something went wrong: {}", e),
         };
 
@@ -206,6 +247,8 @@ impl IntoResponse for AppError {
 
         let inner_exception = match self {
             Self::NetworkFailure(ref e) => Some(e),
+            Self::Timeout(ref e) => Some(e),
+            Self::ResourceExhausted(ref e) => Some(e),
             Self::Other(ref e) => Some(e),
             _ => None,
         };
@@ -225,6 +268,24 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self::Other(err.into())
+        let err = err.into();
+
+        if is_timeout(&err) {
+            Self::Timeout(err)
+        } else if exhausted_resource(&err).is_some() {
+            Self::ResourceExhausted(err)
+        } else {
+            Self::Other(err)
+        }
     }
 }
+
+fn is_timeout(error: &anyhow::Error) -> bool {
+    // `downcast_ref` also looks into the context attached to the error, which `chain` doesn't.
+    error.downcast_ref::<RequestTimeoutError>().is_some()
+        || error.chain().any(|cause| cause.is::<RequestTimeoutError>())
+}
+
+fn exhausted_resource(error: &anyhow::Error) -> Option<&ResourceExhaustedError> {
+    error.downcast_ref::<ResourceExhaustedError>()
+}
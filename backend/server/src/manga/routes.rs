@@ -80,13 +80,15 @@ async fn get_mangas(
     StateExtractor(State {
         database,
         source_manager,
+        settings,
         ..
     }): StateExtractor<State>,
     Query(GetMangasQuery { q }): Query<GetMangasQuery>,
 ) -> Result<Json<Vec<Manga>>, AppError> {
+    let concurrent_requests = settings.lock().await.search_concurrency();
     let source_manager = &*source_manager.lock().await;
     let results = cancel_after(Duration::from_secs(15), |token| {
-        usecases::search_mangas(source_manager, &database, token, q)
+        usecases::search_mangas(source_manager, &database, token, q, concurrent_requests)
     })
     .await
     .map_err(AppError::from_search_mangas_error)?
@@ -183,7 +185,9 @@ impl From<DownloadMangaChapterParams> for ChapterId {
 
 async fn download_manga_chapter(
     StateExtractor(State {
-        chapter_storage, ..
+        chapter_storage,
+        chapter_downloader,
+        ..
     }): StateExtractor<State>,
     SourceExtractor(source): SourceExtractor,
     Path(params): Path<DownloadMangaChapterParams>,
@@ -191,10 +195,16 @@ async fn download_manga_chapter(
 ) -> Result<Json<String>, AppError> {
     let chapter_id = ChapterId::from(params);
     let chapter_storage = &*chapter_storage.lock().await;
-    let output_path =
-        usecases::fetch_manga_chapter(&source, chapter_storage, &chapter_id, chapter_num)
-            .await
-            .map_err(AppError::from_fetch_manga_chapters_error)?;
+    let chapter_downloader = chapter_downloader.lock().await.clone();
+    let output_path = usecases::fetch_manga_chapter(
+        &source,
+        chapter_storage,
+        &chapter_downloader,
+        &chapter_id,
+        chapter_num,
+    )
+    .await
+    .map_err(AppError::from_fetch_manga_chapters_error)?;
 
     Ok(Json(output_path.to_string_lossy().into()))
 }
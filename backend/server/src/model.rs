@@ -1,7 +1,11 @@
 use serde::Serialize;
+use shared::chapter_storage::LegacyChaptersMigration;
 use shared::model::{
     Chapter as DomainChapter, Manga as DomainManga, SourceInformation as DomainSourceInformation,
 };
+use shared::source::{cookie_jar::StoredCookie, StoreMetrics};
+use shared::usecases::list_installed_sources::InstalledSource;
+use shared::usecases::list_source_updates::SourceUpdate;
 
 #[derive(Serialize)]
 pub struct SourceInformation {
@@ -20,6 +24,107 @@ impl From<DomainSourceInformation> for SourceInformation {
     }
 }
 
+#[derive(Serialize)]
+pub struct InstalledSourceInformation {
+    #[serde(flatten)]
+    information: SourceInformation,
+    failed_to_load: bool,
+    load_error: Option<String>,
+}
+
+impl From<InstalledSource> for InstalledSourceInformation {
+    fn from(value: InstalledSource) -> Self {
+        Self {
+            information: value.information.into(),
+            failed_to_load: value.load_error.is_some(),
+            load_error: value.load_error,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SourceUpdateInformation {
+    id: String,
+    name: String,
+    installed_version: usize,
+    available_version: usize,
+}
+
+impl From<SourceUpdate> for SourceUpdateInformation {
+    fn from(value: SourceUpdate) -> Self {
+        Self {
+            id: value.available.information.id.value().clone(),
+            name: value.available.information.name,
+            installed_version: value.installed.version,
+            available_version: value.available.information.version,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SourceCookie {
+    domain: String,
+    path: String,
+    name: String,
+    value: String,
+}
+
+impl From<StoredCookie> for SourceCookie {
+    fn from(value: StoredCookie) -> Self {
+        Self {
+            domain: value.domain,
+            path: value.path,
+            name: value.name,
+            value: value.value,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SourceStoreMetrics {
+    std_descriptors: usize,
+    retained_std_descriptors: usize,
+    html_documents: usize,
+    requests: usize,
+}
+
+impl From<StoreMetrics> for SourceStoreMetrics {
+    fn from(value: StoreMetrics) -> Self {
+        Self {
+            std_descriptors: value.std_descriptors,
+            retained_std_descriptors: value.retained_std_descriptors,
+            html_documents: value.html_documents,
+            requests: value.requests,
+        }
+    }
+}
+
+/// The outcome of renaming the chapters stored with the legacy path format, including the files
+/// which had to be left alone.
+#[derive(Serialize, Clone)]
+pub struct LegacyChaptersMigrationReport {
+    migrated: usize,
+    unmapped: Vec<String>,
+    ambiguous: Vec<String>,
+}
+
+impl From<LegacyChaptersMigration> for LegacyChaptersMigrationReport {
+    fn from(value: LegacyChaptersMigration) -> Self {
+        let paths_to_strings = |paths: Vec<std::path::PathBuf>| {
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect()
+        };
+
+        Self {
+            migrated: value.migrated,
+            unmapped: paths_to_strings(value.unmapped),
+            ambiguous: paths_to_strings(value.ambiguous),
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct Manga {
     // FIXME maybe both `id` and `source_id` should be encoded into a single field
@@ -1,10 +1,12 @@
 use anyhow::Context;
 use axum::extract::State as StateExtractor;
-use axum::routing::{get, put};
+use axum::routing::{get, post, put};
 use axum::{Json, Router};
+use log::error;
 use shared::usecases;
 use shared::usecases::update_settings::UpdateableSettings;
 
+use crate::model::LegacyChaptersMigrationReport;
 use crate::state::State;
 use crate::AppError;
 
@@ -12,6 +14,7 @@ pub fn routes() -> Router<State> {
     Router::new()
         .route("/settings", get(get_settings))
         .route("/settings", put(update_settings))
+        .route("/legacy-chapters/migrate", post(migrate_legacy_chapters))
 }
 
 async fn get_settings(
@@ -23,6 +26,8 @@ async fn get_settings(
 async fn update_settings(
     StateExtractor(State {
         chapter_storage,
+        chapter_downloader,
+        database,
         settings,
         settings_path,
         ..
@@ -34,9 +39,10 @@ async fn update_settings(
 
     // Update the chapter storage for the new storage path
     if let Some(storage_path) = settings.storage_path.as_ref() {
+        let mut chapter_storage = chapter_storage.lock().await;
+        let storage_path_changed = chapter_storage.downloads_folder_path() != storage_path;
+
         chapter_storage
-            .lock()
-            .await
             .set_downloads_folder_path(storage_path.clone())
             .with_context(|| {
                 format!(
@@ -44,7 +50,37 @@ async fn update_settings(
                     storage_path.display()
                 )
             })?;
+
+        // The new folder might have chapters stored with the legacy path format.
+        if storage_path_changed {
+            let _ = usecases::migrate_legacy_chapter_paths(&database, &mut chapter_storage)
+                .await
+                .inspect_err(|e| error!("couldn't migrate legacy chapter paths: {e:?}"));
+        }
     }
 
+    // Downloads which are already running keep using the previous settings.
+    chapter_downloader
+        .lock()
+        .await
+        .update_settings(&settings)
+        .context("Couldn't apply the new download settings")?;
+
     Ok(Json(UpdateableSettings::from(&*settings)))
 }
+
+/// Renames the chapters stored with the legacy path format, reporting the files which had to be
+/// left alone.
+async fn migrate_legacy_chapters(
+    StateExtractor(State {
+        chapter_storage,
+        database,
+        ..
+    }): StateExtractor<State>,
+) -> Result<Json<LegacyChaptersMigrationReport>, AppError> {
+    let migration =
+        usecases::migrate_legacy_chapter_paths(&database, &mut *chapter_storage.lock().await)
+            .await?;
+
+    Ok(Json(migration.into()))
+}
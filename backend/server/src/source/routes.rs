@@ -9,7 +9,10 @@ use shared::settings::SourceSettingValue;
 use shared::source::model::SettingDefinition;
 use shared::usecases;
 
-use crate::model::SourceInformation;
+use crate::model::{
+    InstalledSourceInformation, SourceCookie, SourceInformation, SourceStoreMetrics,
+    SourceUpdateInformation,
+};
 use crate::source_extractor::{SourceExtractor, SourceParams};
 use crate::state::State;
 use crate::AppError;
@@ -22,6 +25,7 @@ pub fn routes() -> Router<State> {
             post(install_source),
         )
         .route("/installed-sources", get(list_installed_sources))
+        .route("/installed-sources/updates", get(list_source_updates))
         .route("/installed-sources/:source_id", delete(uninstall_source))
         .route(
             "/installed-sources/:source_id/setting-definitions",
@@ -35,13 +39,29 @@ pub fn routes() -> Router<State> {
             "/installed-sources/:source_id/stored-settings",
             post(set_source_stored_settings),
         )
+        .route(
+            "/installed-sources/:source_id/cookies",
+            get(get_source_cookies),
+        )
+        .route(
+            "/installed-sources/:source_id/cookies",
+            delete(clear_source_cookies),
+        )
+        .route(
+            "/installed-sources/:source_id/store-metrics",
+            get(get_source_store_metrics),
+        )
 }
 
 async fn list_available_sources(
-    StateExtractor(State { settings, .. }): StateExtractor<State>,
+    StateExtractor(State {
+        settings,
+        http_client,
+        ..
+    }): StateExtractor<State>,
 ) -> Result<Json<Vec<SourceInformation>>, AppError> {
     let source_lists = settings.lock().await.source_lists.clone();
-    let available_sources = usecases::list_available_sources(source_lists)
+    let available_sources = usecases::list_available_sources(&http_client, source_lists)
         .await?
         .into_iter()
         .map(SourceInformation::from)
@@ -59,11 +79,13 @@ async fn install_source(
     StateExtractor(State {
         source_manager,
         settings,
+        http_client,
         ..
     }): StateExtractor<State>,
     Path(InstallSourceParams { source_id }): Path<InstallSourceParams>,
 ) -> Result<Json<()>, AppError> {
     usecases::install_source(
+        &http_client,
         &mut *source_manager.lock().await,
         &settings.lock().await.source_lists,
         SourceId::new(source_id),
@@ -75,15 +97,39 @@ async fn install_source(
 
 async fn list_installed_sources(
     StateExtractor(State { source_manager, .. }): StateExtractor<State>,
-) -> Json<Vec<SourceInformation>> {
+) -> Json<Vec<InstalledSourceInformation>> {
     let installed_sources = usecases::list_installed_sources(&*source_manager.lock().await)
         .into_iter()
-        .map(SourceInformation::from)
+        .map(InstalledSourceInformation::from)
         .collect();
 
     Json(installed_sources)
 }
 
+async fn list_source_updates(
+    StateExtractor(State {
+        source_manager,
+        settings,
+        http_client,
+        ..
+    }): StateExtractor<State>,
+) -> Result<Json<Vec<SourceUpdateInformation>>, AppError> {
+    let installed_sources = usecases::list_installed_sources(&*source_manager.lock().await)
+        .into_iter()
+        .map(|installed_source| installed_source.information)
+        .collect();
+    let source_lists = settings.lock().await.source_lists.clone();
+
+    let source_updates =
+        usecases::list_source_updates(&http_client, source_lists, installed_sources)
+            .await?
+            .into_iter()
+            .map(SourceUpdateInformation::from)
+            .collect();
+
+    Ok(Json(source_updates))
+}
+
 async fn uninstall_source(
     StateExtractor(State { source_manager, .. }): StateExtractor<State>,
     Path(SourceParams { source_id }): Path<SourceParams>,
@@ -129,3 +175,26 @@ async fn set_source_stored_settings(
 
     Ok(Json(()))
 }
+
+async fn get_source_cookies(SourceExtractor(source): SourceExtractor) -> Json<Vec<SourceCookie>> {
+    let cookies = usecases::get_source_cookies(&source)
+        .into_iter()
+        .map(SourceCookie::from)
+        .collect();
+
+    Json(cookies)
+}
+
+async fn clear_source_cookies(
+    SourceExtractor(source): SourceExtractor,
+) -> Result<Json<()>, AppError> {
+    usecases::clear_source_cookies(&source)?;
+
+    Ok(Json(()))
+}
+
+async fn get_source_store_metrics(
+    SourceExtractor(source): SourceExtractor,
+) -> Json<SourceStoreMetrics> {
+    Json(usecases::get_source_store_metrics(&source).into())
+}
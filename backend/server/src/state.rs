@@ -2,8 +2,8 @@ use std::{path::PathBuf, sync::Arc};
 
 use axum_macros::FromRef;
 use shared::{
-    chapter_storage::ChapterStorage, database::Database, settings::Settings,
-    source_manager::SourceManager,
+    chapter_downloader::ChapterDownloader, chapter_storage::ChapterStorage, database::Database,
+    settings::Settings, source_manager::SourceManager,
 };
 use tokio::sync::Mutex;
 
@@ -14,6 +14,9 @@ pub struct State {
     pub source_manager: Arc<Mutex<SourceManager>>,
     pub database: Arc<Database>,
     pub chapter_storage: Arc<Mutex<ChapterStorage>>,
+    pub chapter_downloader: Arc<Mutex<ChapterDownloader>>,
+    /// Client used for requests not made by sources (e.g. fetching source lists).
+    pub http_client: reqwest::Client,
     pub settings: Arc<Mutex<Settings>>,
     pub settings_path: PathBuf,
     pub job_state: JobState,
@@ -1,5 +1,6 @@
 use crate::get_build_info;
 use axum::{
+    extract::State as StateExtractor,
     routing::{get, post},
     Json, Router,
 };
@@ -15,11 +16,13 @@ pub fn routes() -> Router<AppState> {
         .route("/update/install", post(install_update_handler))
 }
 
-async fn check_for_updates() -> Result<Json<check_update::CheckUpdateResponse>, crate::AppError> {
+async fn check_for_updates(
+    StateExtractor(AppState { http_client, .. }): StateExtractor<AppState>,
+) -> Result<Json<check_update::CheckUpdateResponse>, crate::AppError> {
     let build_info = get_build_info()
         .ok_or_else(|| AppError::Other(anyhow::anyhow!("Could not get build info")))?;
 
-    let response = check_update::check_update(build_info.version).await?;
+    let response = check_update::check_update(&http_client, build_info.version).await?;
     Ok(Json(response))
 }
 
@@ -28,13 +31,14 @@ struct InstallUpdateRequest {
     version: String,
 }
 
-#[axum_macros::debug_handler]
+#[axum_macros::debug_handler(state = AppState)]
 async fn install_update_handler(
+    StateExtractor(AppState { http_client, .. }): StateExtractor<AppState>,
     Json(request): Json<InstallUpdateRequest>,
 ) -> Result<Json<()>, crate::AppError> {
     let build_info = get_build_info()
         .ok_or_else(|| AppError::Other(anyhow::anyhow!("Could not get build info")))?;
 
-    install_update::install_update(request.version, build_info.build).await?;
+    install_update::install_update(&http_client, request.version, build_info.build).await?;
     Ok(Json(()))
 }
@@ -3,9 +3,10 @@ use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use futures::executor;
 use pprof::criterion::{Output, PProfProfiler};
 use shared::{
-    chapter_downloader::download_chapter_pages_as_cbz, settings::Settings, source::Source,
+    chapter_downloader::ChapterDownloader, settings::Settings, source::Source,
+    util::ConnectivityChecker,
 };
-use std::{env, io, path::PathBuf};
+use std::{env, io, path::PathBuf, sync::Arc};
 use tokio_util::sync::CancellationToken;
 
 pub fn chapter_downloader_benchmark(c: &mut Criterion) {
@@ -14,7 +15,10 @@ pub fn chapter_downloader_benchmark(c: &mut Criterion) {
     let chapter_id = env::var("BENCHMARK_CHAPTER_ID").unwrap();
     let settings = Settings::default();
 
-    let source = Source::from_aix_file(source_path.as_ref(), settings).unwrap();
+    let chapter_downloader = ChapterDownloader::new(&settings).unwrap();
+    let connectivity_checker = Arc::new(ConnectivityChecker::new(&settings.network));
+    let source =
+        Source::from_aix_file(source_path.as_ref(), settings, connectivity_checker).unwrap();
     let pages = executor::block_on(source.get_page_list(
         CancellationToken::new(),
         manga_id,
@@ -24,10 +28,16 @@ pub fn chapter_downloader_benchmark(c: &mut Criterion) {
     .unwrap();
 
     let runtime = tokio::runtime::Runtime::new().unwrap();
+    let partial_download_folder = tempfile::tempdir().unwrap();
 
     c.bench_function("download_chapter_pages_as_cbz", |b| {
         b.to_async(&runtime).iter(|| {
-            download_chapter_pages_as_cbz(io::Cursor::new(Vec::new()), &source, pages.clone())
+            chapter_downloader.download_chapter_pages_as_cbz(
+                io::Cursor::new(Vec::new()),
+                &source,
+                pages.clone(),
+                partial_download_folder.path(),
+            )
         })
     });
 }
@@ -12,6 +12,7 @@ pub fn search_mangas_benchmark(c: &mut Criterion) {
     let query = env::var("BENCHMARK_QUERY").unwrap();
     let settings = Settings::default();
 
+    let concurrent_requests = settings.search_concurrency();
     let source_manager = SourceManager::from_folder(sources_path, settings).unwrap();
 
     let runtime = tokio::runtime::Runtime::new().unwrap();
@@ -25,6 +26,7 @@ pub fn search_mangas_benchmark(c: &mut Criterion) {
                 &db,
                 CancellationToken::new(),
                 query.clone(),
+                concurrent_requests,
             )
             .await
             .unwrap();
@@ -0,0 +1,41 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Limits how many bytes per second can be transferred, across everyone sharing the limiter.
+pub struct BandwidthLimiter {
+    bytes_per_second: u64,
+    next_available_at: Mutex<Instant>,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second.max(1),
+            next_available_at: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    /// Accounts for `bytes` that were just transferred, waiting until the transfer fits inside
+    /// the limit.
+    pub async fn consume(&self, bytes: usize) {
+        let delay = {
+            let mut next_available_at = self.next_available_at.lock().unwrap();
+            let now = Instant::now();
+            let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64);
+
+            *next_available_at = (*next_available_at).max(now) + cost;
+
+            next_available_at.saturating_duration_since(now)
+        };
+
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
@@ -1,79 +1,523 @@
-use futures::{stream, StreamExt, TryStreamExt};
+use futures::{stream, StreamExt};
+use log::{debug, warn};
+use reqwest::{
+    cookie::CookieStore as _,
+    header::{CONTENT_TYPE, SET_COOKIE},
+    Request,
+};
 use std::{
+    collections::{HashMap, HashSet},
+    fs,
     io::Seek,
     io::Write,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
 };
 use tempfile::NamedTempFile;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio_util::sync::CancellationToken;
 
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
+use base64::{
+    alphabet,
+    engine::{general_purpose, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine as _,
+};
+use sha2::{Digest, Sha256};
+use url::Url;
 use zip::{write::FileOptions, CompressionMethod, ZipWriter};
 
 use crate::{
+    bandwidth_limiter::BandwidthLimiter,
     chapter_storage::ChapterStorage,
+    http::{self, RetryPolicy},
+    image_processing,
     model::ChapterId,
-    source::{model::Page, Source},
+    settings::{ImageProcessingSettings, Settings},
+    source::{cookie_jar::SourceCookieJar, model::Page, Source},
 };
 
-const CONCURRENT_REQUESTS: usize = 4;
-
-pub async fn ensure_chapter_is_in_storage(
-    chapter_storage: &ChapterStorage,
-    source: &Source,
-    chapter_id: &ChapterId,
-    chapter_num: Option<f64>,
-) -> Result<PathBuf, Error> {
-    if let Some(path) = chapter_storage.get_stored_chapter(chapter_id) {
-        return Ok(path);
-    }
-
-    // FIXME like downloaderror is a really bad name??
-    let pages = source
-        .get_page_list(
-            CancellationToken::new(),
-            chapter_id.manga_id().value().clone(),
-            chapter_id.value().clone(),
-            chapter_num,
-        )
-        .await
-        .with_context(|| "Failed to get page list")
-        .map_err(Error::DownloadError)?;
-
-    if pages.is_empty() {
-        return Err(Error::DownloadError(anyhow!(
-            "No pages found for chapter {}",
-            chapter_id.value()
-        )));
-    }
-
-    // FIXME this logic should be contained entirely within the storage..? maybe we could return something that's writable
-    // and then commit it into the storage (or maybe a implicit commit on drop, but i dont think it works well as there
-    // could be errors while committing it)
-    let output_path = chapter_storage.get_path_to_store_chapter(chapter_id);
-
-    // Write chapter pages to a temporary file, so that if things go wrong
-    // we do not have a borked .cbz file in the chapter storage.
-    let temporary_file =
-        NamedTempFile::new_in(output_path.parent().unwrap()).map_err(|e| Error::Other(e.into()))?;
-    download_chapter_pages_as_cbz(&temporary_file, source, pages)
-        .await
-        .with_context(|| "Failed to download chapter pages")
-        .map_err(Error::DownloadError)?;
-
-    // If we succeeded downloading all the chapter pages, persist our temporary
-    // file into the chapter storage definitively.
-    chapter_storage
-        .persist_chapter(chapter_id, temporary_file)
-        .with_context(|| {
-            format!(
-                "Failed to persist chapter {} into storage",
-                chapter_id.value()
+/// Pages are retried more than other requests, as a single failed page fails the whole chapter.
+const PAGE_RETRY_POLICY: RetryPolicy = RetryPolicy {
+    max_retries: 3,
+    initial_backoff: Duration::from_millis(500),
+};
+
+/// Text pages are stored as plain text files inside the archive.
+const TEXT_PAGE_EXTENSION: &str = "txt";
+
+/// Extension used for pages whose format we couldn't figure out. Readers look at the contents
+/// of the images anyway, they only need the extension to be one of an image.
+const UNKNOWN_PAGE_EXTENSION: &str = "jpg";
+
+/// File inside a partial download folder identifying the page list its pages were downloaded
+/// from, so that we never resume from pages of a page list that has since changed.
+const PAGE_LIST_HASH_FILE_NAME: &str = "pages.sha256";
+
+const BASE64_ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Downloads chapters from sources into the chapter storage.
+#[derive(Clone)]
+pub struct ChapterDownloader {
+    client: reqwest::Client,
+    /// Client used for the sources which are allowed to serve images with invalid certificates.
+    insecure_client: reqwest::Client,
+    sources_accepting_invalid_certificates: HashSet<String>,
+    image_processing_settings: ImageProcessingSettings,
+    concurrent_requests: usize,
+    max_connections_per_host: Option<usize>,
+    // Those are shared between all clones of the downloader, so that the limits apply to all
+    // downloads happening at the same time.
+    host_connection_limits: Arc<Mutex<HashMap<String, Weak<Semaphore>>>>,
+    bandwidth_limiter: Option<Arc<BandwidthLimiter>>,
+}
+
+impl ChapterDownloader {
+    pub fn new(settings: &Settings) -> anyhow::Result<Self> {
+        let client = http::download_client_builder(&settings.network)?
+            .build()
+            .context("couldn't build HTTP client")?;
+        // Some sources return invalid certs, but otherwise download images just fine...
+        let insecure_client = http::download_client_builder(&settings.network)?
+            .danger_accept_invalid_certs(true)
+            .build()
+            .context("couldn't build HTTP client")?;
+        let sources_accepting_invalid_certificates = settings
+            .source_network_settings
+            .iter()
+            .filter(|(_, network_settings)| network_settings.accept_invalid_certificates)
+            .map(|(source_id, _)| source_id.clone())
+            .collect();
+
+        Ok(Self {
+            client,
+            insecure_client,
+            sources_accepting_invalid_certificates,
+            image_processing_settings: settings.image_processing.clone(),
+            concurrent_requests: settings.chapter_download_concurrency(),
+            max_connections_per_host: settings.max_connections_per_host.map(|max| max.max(1)),
+            host_connection_limits: Default::default(),
+            bandwidth_limiter: settings
+                .download_speed_limit_kb
+                .map(|limit| Arc::new(BandwidthLimiter::new(limit.saturating_mul(1000)))),
+        })
+    }
+
+    /// Applies new settings to the downloads started from now on.
+    ///
+    /// The per-host connection limits and the bandwidth limiter are kept when their settings
+    /// didn't change, so that they're still shared with the downloads already running.
+    pub fn update_settings(&mut self, settings: &Settings) -> anyhow::Result<()> {
+        let mut updated = Self::new(settings)?;
+
+        if updated.max_connections_per_host == self.max_connections_per_host {
+            updated.host_connection_limits = self.host_connection_limits.clone();
+        }
+
+        let bytes_per_second = |downloader: &Self| {
+            downloader
+                .bandwidth_limiter
+                .as_ref()
+                .map(|bandwidth_limiter| bandwidth_limiter.bytes_per_second())
+        };
+        if bytes_per_second(&updated) == bytes_per_second(self) {
+            updated.bandwidth_limiter = self.bandwidth_limiter.clone();
+        }
+
+        *self = updated;
+
+        Ok(())
+    }
+
+    pub async fn ensure_chapter_is_in_storage(
+        &self,
+        chapter_storage: &ChapterStorage,
+        source: &Source,
+        chapter_id: &ChapterId,
+        chapter_num: Option<f64>,
+    ) -> Result<PathBuf, Error> {
+        if let Some(path) = chapter_storage.get_stored_chapter(chapter_id) {
+            return Ok(path);
+        }
+
+        // FIXME like downloaderror is a really bad name??
+        let pages = source
+            .get_page_list(
+                CancellationToken::new(),
+                chapter_id.manga_id().value().clone(),
+                chapter_id.value().clone(),
+                chapter_num,
             )
+            .await
+            .with_context(|| "Failed to get page list")
+            .map_err(Error::DownloadError)?;
+
+        if pages.is_empty() {
+            return Err(Error::DownloadError(anyhow!(
+                "No pages found for chapter {}",
+                chapter_id.value()
+            )));
+        }
+
+        // FIXME this logic should be contained entirely within the storage..? maybe we could return something that's writable
+        // and then commit it into the storage (or maybe a implicit commit on drop, but i dont think it works well as there
+        // could be errors while committing it)
+        let output_path = chapter_storage.get_path_to_store_chapter(chapter_id);
+        let partial_download_path = chapter_storage.get_partial_download_path(chapter_id);
+
+        // Write chapter pages to a temporary file, so that if things go wrong
+        // we do not have a borked .cbz file in the chapter storage.
+        let temporary_file = NamedTempFile::new_in(output_path.parent().unwrap())
+            .map_err(|e| Error::Other(e.into()))?;
+        self.download_chapter_pages_as_cbz(&temporary_file, source, pages, &partial_download_path)
+            .await
+            .with_context(|| "Failed to download chapter pages")
+            .map_err(Error::DownloadError)?;
+
+        // If we succeeded downloading all the chapter pages, persist our temporary
+        // file into the chapter storage definitively.
+        chapter_storage
+            .persist_chapter(chapter_id, temporary_file)
+            .with_context(|| {
+                format!(
+                    "Failed to persist chapter {} into storage",
+                    chapter_id.value()
+                )
+            })
+            .map_err(Error::Other)?;
+
+        // The pages were already packed into the chapter file, so there's nothing to resume anymore.
+        let _ = chapter_storage
+            .remove_partial_download(chapter_id)
+            .inspect_err(|e| warn!("couldn't remove partial download: {e:?}"));
+
+        Ok(output_path)
+    }
+
+    /// Downloads all `pages` into `partial_download_path`, packing them into a .cbz file written
+    /// to `output` as they arrive.
+    ///
+    /// Pages already present in `partial_download_path` (e.g. from a previous attempt that failed
+    /// midway) are not downloaded again, as long as they came from the same page list. If any page
+    /// fails to download, the pages that succeeded are kept there, so that the download can be
+    /// resumed later.
+    pub async fn download_chapter_pages_as_cbz<W>(
+        &self,
+        output: W,
+        source: &Source,
+        pages: Vec<Page>,
+        partial_download_path: &Path,
+    ) -> anyhow::Result<()>
+    where
+        W: Write + Seek,
+    {
+        let mut pages = pages;
+        pages.sort_by_key(|page| page.index);
+
+        let downloaded_pages = prepare_partial_download(partial_download_path, &pages)?;
+        let client = self.client_for_source(&source.manifest().info.id);
+
+        let mut writer = ZipWriter::new(output);
+        let file_options = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+        let page_count = pages.len();
+        let mut downloaded_count = 0;
+        let mut first_error = None;
+
+        // `buffered` yields the pages in order, while still downloading up to `concurrent_requests`
+        // pages at once. Each page is written into the archive as soon as it's its turn, so we only
+        // ever hold a handful of pages at a time.
+        let mut downloaded_pages_stream = stream::iter(pages)
+            .map(|page| {
+                let downloaded_pages = &downloaded_pages;
+
+                async move {
+                    // FIXME we should left pad this number with zeroes up to the maximum
+                    // amount of pages needed, but for now we pad 4 digits
+                    // stop reading the bible if this ever becomes an issue
+                    let page_name = format!("{:0>4}", page.index);
+
+                    let (contents, extension) = match downloaded_pages.get(&page_name) {
+                        Some(path) => {
+                            let extension = path
+                                .extension()
+                                .and_then(|ext| ext.to_str())
+                                .unwrap_or(UNKNOWN_PAGE_EXTENSION)
+                                .to_owned();
+
+                            (tokio::fs::read(path).await?, extension)
+                        }
+                        None => {
+                            let page_index = page.index;
+                            let (contents, extension) = self
+                                .get_page_contents(client, source, page)
+                                .await
+                                .with_context(|| format!("while downloading page {page_index}"))?;
+
+                            let path =
+                                partial_download_path.join(format!("{page_name}.{extension}"));
+                            write_page(partial_download_path, &path, &contents)?;
+
+                            (contents, extension)
+                        }
+                    };
+
+                    self.process_page(page_name, contents, extension).await
+                }
+            })
+            .buffered(self.concurrent_requests);
+
+        while let Some(result) = downloaded_pages_stream.next().await {
+            let entries = match result {
+                Ok(entries) => entries,
+                Err(e) => {
+                    // Keep going, so that the remaining pages are still saved for when the download
+                    // gets resumed.
+                    first_error.get_or_insert(e);
+
+                    continue;
+                }
+            };
+
+            downloaded_count += 1;
+
+            // The archive is useless once a page is missing, so there's no point in writing to it.
+            if first_error.is_some() {
+                continue;
+            }
+
+            for (filename, contents) in entries {
+                writer.start_file(filename, file_options)?;
+                writer.write_all(&contents)?;
+            }
+        }
+
+        if let Some(error) = first_error {
+            return Err(error.context(format!(
+                "only {downloaded_count} out of {page_count} pages were downloaded"
+            )));
+        }
+
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    /// Gets the contents of a page and the extension it should be stored with, either by
+    /// downloading its image or from the data inlined in the page itself.
+    async fn get_page_contents(
+        &self,
+        client: &reqwest::Client,
+        source: &Source,
+        page: Page,
+    ) -> anyhow::Result<(Vec<u8>, String)> {
+        if let Some(image_url) = page.image_url {
+            // The URL's extension isn't used, as it's often the name of a script (e.g. `.php`)
+            // instead of the image's format.
+            let request = source.get_image_request(image_url).await?;
+            let (contents, content_type) = self
+                .fetch_page(client, &source.cookie_jar(), request)
+                .await?;
+            let extension = detect_image_extension(&contents, content_type.as_deref())?
+                .unwrap_or(UNKNOWN_PAGE_EXTENSION)
+                .to_owned();
+
+            return Ok((contents, extension));
+        }
+
+        if let Some(data) = page.base64 {
+            // Some sources send a whole data URL (`data:image/png;base64,...`) instead of just
+            // the encoded data.
+            let (media_type, data) = match data
+                .strip_prefix("data:")
+                .and_then(|data_url| data_url.split_once(','))
+            {
+                Some((header, data)) => (header.split(';').next(), data),
+                None => (None, data.as_str()),
+            };
+
+            let contents = decode_base64(data).context("couldn't decode base64 page")?;
+            let extension = detect_image_extension(&contents, media_type)?
+                .unwrap_or(UNKNOWN_PAGE_EXTENSION)
+                .to_owned();
+
+            return Ok((contents, extension));
+        }
+
+        if let Some(text) = page.text {
+            return Ok((text.into_bytes(), TEXT_PAGE_EXTENSION.to_owned()));
+        }
+
+        bail!("page has no image URL, base64 data or text")
+    }
+
+    /// Fetches a page, retrying with an exponential backoff when the failure might be temporary
+    /// (network errors, server errors or rate limiting).
+    ///
+    /// Returns the page's contents, along with its `Content-Type`. Cookies set by the image host
+    /// are stored into the source's cookie jar, so they're sent along with the next requests.
+    async fn fetch_page(
+        &self,
+        client: &reqwest::Client,
+        cookie_jar: &SourceCookieJar,
+        request: Request,
+    ) -> anyhow::Result<(Vec<u8>, Option<String>)> {
+        let mut retry = 0;
+
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .ok_or_else(|| anyhow!("couldn't clone the page request"))?;
+
+            let result = async {
+                let _host_connection = self.acquire_host_connection(request.url()).await;
+
+                let response = client.execute(attempt_request).await?;
+                cookie_jar.set_cookies(
+                    &mut response.headers().get_all(SET_COOKIE).iter(),
+                    response.url(),
+                );
+
+                let response = response.error_for_status()?;
+                let content_type = response
+                    .headers()
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| value.to_owned());
+
+                let mut contents = Vec::new();
+                let mut body = response.bytes_stream();
+                while let Some(chunk) = body.next().await {
+                    let chunk = chunk?;
+
+                    if let Some(bandwidth_limiter) = &self.bandwidth_limiter {
+                        bandwidth_limiter.consume(chunk.len()).await;
+                    }
+
+                    contents.extend_from_slice(&chunk);
+                }
+
+                Ok((contents, content_type))
+            }
+            .await;
+
+            match result {
+                Ok(result) => return Ok(result),
+                Err(e) if retry < PAGE_RETRY_POLICY.max_retries && is_retryable(&e) => {
+                    retry += 1;
+                    let backoff = PAGE_RETRY_POLICY.backoff(retry);
+
+                    warn!(
+                        "fetch_page: attempt {retry}/{} to fetch {} failed, retrying in \
+                        {backoff:?}: {e}",
+                        PAGE_RETRY_POLICY.max_retries + 1,
+                        request.url()
+                    );
+
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn client_for_source(&self, source_id: &str) -> &reqwest::Client {
+        if self
+            .sources_accepting_invalid_certificates
+            .contains(source_id)
+        {
+            &self.insecure_client
+        } else {
+            &self.client
+        }
+    }
+
+    /// Waits until a new connection to the host of `url` can be opened, if there's a limit on
+    /// connections per host. The connection slot is freed when the returned permit is dropped.
+    async fn acquire_host_connection(&self, url: &Url) -> Option<OwnedSemaphorePermit> {
+        let max_connections_per_host = self.max_connections_per_host?;
+        let host = url.host_str()?;
+
+        let semaphore = {
+            let mut host_connection_limits = self.host_connection_limits.lock().unwrap();
+
+            // Semaphores are only kept alive by whoever holds or waits for a permit, so the hosts
+            // nobody is downloading from anymore can be forgotten.
+            host_connection_limits.retain(|_, semaphore| semaphore.strong_count() > 0);
+
+            match host_connection_limits
+                .get(host)
+                .and_then(|semaphore| semaphore.upgrade())
+            {
+                Some(semaphore) => semaphore,
+                None => {
+                    let semaphore = Arc::new(Semaphore::new(max_connections_per_host));
+                    host_connection_limits.insert(host.to_owned(), Arc::downgrade(&semaphore));
+
+                    semaphore
+                }
+            }
+        };
+
+        semaphore.acquire_owned().await.ok()
+    }
+
+    /// Applies the configured image processing to a page, returning the file names and contents
+    /// of the entries that should be written into the archive.
+    async fn process_page(
+        &self,
+        page_name: String,
+        contents: Vec<u8>,
+        extension: String,
+    ) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+        if !self.image_processing_settings.is_enabled()
+            || extension == TEXT_PAGE_EXTENSION
+            || !image_processing::can_process(&contents)
+        {
+            return Ok(vec![(format!("{page_name}.{extension}"), contents)]);
+        }
+
+        let settings = self.image_processing_settings.clone();
+        let (contents, result) = tokio::task::spawn_blocking(move || {
+            let result = image_processing::process_page(&settings, &contents);
+
+            (contents, result)
         })
-        .map_err(Error::Other)?;
+        .await?;
+
+        let pieces = match result {
+            Ok(pieces) => pieces,
+            Err(e) => {
+                warn!("couldn't process page {page_name}, storing it unchanged: {e:?}");
+
+                return Ok(vec![(format!("{page_name}.{extension}"), contents)]);
+            }
+        };
+
+        let entries = if pieces.len() == 1 {
+            pieces
+                .into_iter()
+                .map(|piece| (format!("{page_name}.{}", piece.extension), piece.contents))
+                .collect()
+        } else {
+            pieces
+                .into_iter()
+                .enumerate()
+                .map(|(index, piece)| {
+                    (
+                        format!("{page_name}-{index:0>2}.{}", piece.extension),
+                        piece.contents,
+                    )
+                })
+                .collect()
+        };
 
-    Ok(output_path)
+        Ok(entries)
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -84,61 +528,350 @@ pub enum Error {
     Other(#[from] anyhow::Error),
 }
 
-pub async fn download_chapter_pages_as_cbz<W>(
-    output: W,
-    source: &Source,
-    pages: Vec<Page>,
-) -> anyhow::Result<()>
-where
-    W: Write + Seek,
-{
-    let mut writer = ZipWriter::new(output);
-    let client = reqwest::Client::builder()
-        // Some sources return invalid certs, but otherwise download images just fine...
-        // This is kinda bad.
-        .danger_accept_invalid_certs(true)
-        .build()
-        .unwrap();
-    let file_options = FileOptions::default().compression_method(CompressionMethod::Stored);
-
-    stream::iter(pages)
-        .map(|page| {
-            let client = &client;
-
-            async move {
-                let image_url = page.image_url.ok_or(anyhow!("page has no image URL"))?;
-                let extension = Path::new(image_url.path())
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .unwrap_or("jpg")
-                    .to_owned();
-
-                // FIXME we should left pad this number with zeroes up to the maximum
-                // amount of pages needed, but for now we pad 4 digits
-                // stop reading the bible if this ever becomes an issue
-                let filename = format!("{:0>4}.{}", page.index, extension);
-
-                // TODO we could stream the data from the client into the file
-                // would save a bit of memory but i dont think its a big deal
-                let request = source.get_image_request(image_url).await?;
-                let response_bytes = client
-                    .execute(request)
-                    .await?
-                    .error_for_status()?
-                    .bytes()
-                    .await?;
-
-                anyhow::Ok((filename, response_bytes))
-            }
-        })
-        .buffer_unordered(CONCURRENT_REQUESTS)
-        .try_collect::<Vec<_>>()
-        .await?
-        .into_iter()
-        .try_for_each(|(filename, response_bytes)| {
-            writer.start_file(filename, file_options)?;
-            writer.write_all(response_bytes.as_ref())?;
-
-            Ok(())
+/// Figures out which extension a page should be stored with, based on its contents (or on its
+/// media type, if we don't recognize the contents).
+///
+/// Fails if the page is actually an HTML document, which some servers return with a successful
+/// status instead of an error.
+fn detect_image_extension(
+    contents: &[u8],
+    media_type: Option<&str>,
+) -> anyhow::Result<Option<&'static str>> {
+    if let Some(extension) = sniff_image_extension(contents) {
+        return Ok(Some(extension));
+    }
+
+    let media_type = media_type.map(|media_type| {
+        media_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+    });
+
+    if media_type.as_deref() == Some("text/html") || looks_like_html(contents) {
+        bail!("expected an image, but got an HTML page instead");
+    }
+
+    Ok(media_type.as_deref().and_then(extension_for_media_type))
+}
+
+fn sniff_image_extension(contents: &[u8]) -> Option<&'static str> {
+    let extension = match contents {
+        [0xFF, 0xD8, 0xFF, ..] => "jpg",
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', ..] => "png",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "webp",
+        [_, _, _, _, b'f', b't', b'y', b'p', b'a', b'v', b'i', b'f' | b's', ..] => "avif",
+        [b'B', b'M', ..] => "bmp",
+        _ => return None,
+    };
+
+    Some(extension)
+}
+
+fn looks_like_html(contents: &[u8]) -> bool {
+    const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+    let contents = contents.strip_prefix(UTF8_BOM).unwrap_or(contents);
+    let start = contents
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(contents.len());
+    let prefix: Vec<u8> = contents[start..]
+        .iter()
+        .take(16)
+        .map(u8::to_ascii_lowercase)
+        .collect();
+
+    [b"<!doctype html".as_slice(), b"<html", b"<head", b"<body"]
+        .iter()
+        .any(|tag| prefix.starts_with(tag))
+}
+
+fn extension_for_media_type(media_type: &str) -> Option<&'static str> {
+    let extension = match media_type.trim().to_ascii_lowercase().as_str() {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/avif" => "avif",
+        "image/bmp" => "bmp",
+        _ => return None,
+    };
+
+    Some(extension)
+}
+
+fn decode_base64(data: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    // The encoded data is often wrapped into lines, which the decoder doesn't accept.
+    let data: Vec<u8> = data
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+
+    BASE64_ENGINE.decode(data)
+}
+
+fn is_retryable(error: &reqwest::Error) -> bool {
+    match error.status() {
+        Some(status) => PAGE_RETRY_POLICY.should_retry_status(status),
+        // A connection dropped while reading a compressed body surfaces as a decoding error.
+        None => PAGE_RETRY_POLICY.should_retry_error(error) || error.is_decode(),
+    }
+}
+
+/// Ensures the partial download folder exists and matches `pages`, returning the pages that were
+/// already downloaded into it.
+///
+/// If the pages in there were downloaded from a different page list (e.g. the chapter was
+/// updated since the download was interrupted), they're thrown away. Query strings are left out
+/// of the comparison, as many sources sign their image URLs again every time the page list is
+/// fetched.
+fn prepare_partial_download(
+    partial_download_path: &Path,
+    pages: &[Page],
+) -> anyhow::Result<HashMap<String, PathBuf>> {
+    let page_list_hash = page_list_hash(pages);
+    let page_list_hash_path = partial_download_path.join(PAGE_LIST_HASH_FILE_NAME);
+
+    match fs::read_to_string(&page_list_hash_path) {
+        Ok(stored_hash) if stored_hash == page_list_hash => {
+            return find_downloaded_pages(partial_download_path);
+        }
+        Ok(_) => {
+            debug!(
+                "prepare_partial_download: page list changed, discarding the pages in {}",
+                partial_download_path.display()
+            );
+
+            fs::remove_dir_all(partial_download_path)?;
+        }
+        // Folders without a hash come from older versions, which didn't check the page list.
+        Err(_) if partial_download_path.exists() => fs::remove_dir_all(partial_download_path)?,
+        Err(_) => {}
+    }
+
+    fs::create_dir_all(partial_download_path).with_context(|| {
+        format!(
+            "while creating the partial download folder at {}",
+            partial_download_path.display()
+        )
+    })?;
+    fs::write(&page_list_hash_path, page_list_hash)?;
+
+    Ok(HashMap::new())
+}
+
+fn page_list_hash(pages: &[Page]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(pages.len().to_le_bytes());
+
+    for page in pages {
+        hasher.update(page.index.to_le_bytes());
+
+        let image_url_without_query = page.image_url.as_ref().map(|url| {
+            let mut url = url.clone();
+            url.set_query(None);
+            url.set_fragment(None);
+
+            url
+        });
+
+        for field in [
+            image_url_without_query.as_ref().map(|url| url.as_str()),
+            page.base64.as_deref(),
+            page.text.as_deref(),
+        ] {
+            // Prefix each field with its length, so that different pages can't hash the same.
+            let field = field.unwrap_or_default();
+            hasher.update(field.len().to_le_bytes());
+            hasher.update(field.as_bytes());
+        }
+    }
+
+    general_purpose::URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
+
+/// Lists the pages already downloaded into `partial_download_path`, keyed by their file name
+/// without the extension.
+fn find_downloaded_pages(partial_download_path: &Path) -> anyhow::Result<HashMap<String, PathBuf>> {
+    let mut downloaded_pages = HashMap::new();
+
+    for entry in fs::read_dir(partial_download_path)? {
+        let path = entry?.path();
+        let Some(page_name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+
+        if page_name.chars().all(|c| c.is_ascii_digit()) {
+            downloaded_pages.insert(page_name.to_owned(), path);
+        }
+    }
+
+    if !downloaded_pages.is_empty() {
+        debug!(
+            "find_downloaded_pages: resuming download with {} pages from {}",
+            downloaded_pages.len(),
+            partial_download_path.display()
+        );
+    }
+
+    Ok(downloaded_pages)
+}
+
+fn write_page(partial_download_path: &Path, path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    // Write to a temporary file first, so that a page which was only partially written (e.g. if
+    // we get killed midway) never gets mistaken for a downloaded one.
+    let mut temporary_file = NamedTempFile::new_in(partial_download_path)?;
+    temporary_file.write_all(contents)?;
+    temporary_file.persist(path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_detects_the_extension_from_the_contents() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let webp = b"RIFF\0\0\0\0WEBPVP8 ";
+
+        assert_eq!(
+            Some("png"),
+            detect_image_extension(png, Some("image/jpeg")).unwrap()
+        );
+        assert_eq!(Some("webp"), detect_image_extension(webp, None).unwrap());
+    }
+
+    #[test]
+    fn it_falls_back_to_the_media_type() {
+        assert_eq!(
+            Some("jpg"),
+            detect_image_extension(b"????", Some("image/jpeg; charset=binary")).unwrap()
+        );
+        assert_eq!(None, detect_image_extension(b"????", None).unwrap());
+    }
+
+    #[test]
+    fn it_decodes_wrapped_base64() {
+        assert_eq!(
+            b"wrapped page".to_vec(),
+            decode_base64(" d3JhcHBl\r\nZCBwYWdl\n").unwrap()
+        );
+    }
+
+    #[test]
+    fn it_discards_pages_downloaded_from_another_page_list() -> anyhow::Result<()> {
+        let partial_download_folder = tempfile::tempdir()?;
+        let partial_download_path = partial_download_folder.path().join("chapter");
+        let page = |url: &str| Page {
+            image_url: Some(Url::parse(url).unwrap()),
+            ..Default::default()
+        };
+        let pages = vec![page("https://example.com/1.jpg")];
+
+        assert!(prepare_partial_download(&partial_download_path, &pages)?.is_empty());
+        fs::write(partial_download_path.join("0000.jpg"), b"page")?;
+        assert_eq!(
+            prepare_partial_download(&partial_download_path, &pages)?.len(),
+            1
+        );
+
+        let resigned_pages = vec![page("https://example.com/1.jpg?token=new")];
+        assert_eq!(
+            prepare_partial_download(&partial_download_path, &resigned_pages)?.len(),
+            1
+        );
+
+        let updated_pages = vec![page("https://example.com/updated-1.jpg")];
+
+        assert!(prepare_partial_download(&partial_download_path, &updated_pages)?.is_empty());
+        assert!(!partial_download_path.join("0000.jpg").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_html_pages() {
+        assert!(detect_image_extension(b"\n  <!DOCTYPE html><html>", None).is_err());
+        assert!(detect_image_extension(b"Not Found", Some("text/html")).is_err());
+        assert!(detect_image_extension(b"\xEF\xBB\xBF\r\n<HTML lang=\"en\">", None).is_err());
+        assert!(detect_image_extension(b"\t<head><title>Error</title>", None).is_err());
+        assert!(detect_image_extension(b"<body>Forbidden</body>", None).is_err());
+    }
+
+    #[test]
+    fn it_keeps_the_shared_limits_across_unrelated_settings_changes() -> anyhow::Result<()> {
+        let mut settings = Settings {
+            max_connections_per_host: Some(2),
+            download_speed_limit_kb: Some(100),
+            ..Default::default()
+        };
+        let mut downloader = ChapterDownloader::new(&settings)?;
+        let host_connection_limits = downloader.host_connection_limits.clone();
+        let bandwidth_limiter = downloader.bandwidth_limiter.clone().unwrap();
+
+        settings.image_processing.grayscale = true;
+        downloader.update_settings(&settings)?;
+
+        assert!(Arc::ptr_eq(
+            &host_connection_limits,
+            &downloader.host_connection_limits
+        ));
+        assert!(Arc::ptr_eq(
+            &bandwidth_limiter,
+            downloader.bandwidth_limiter.as_ref().unwrap()
+        ));
+
+        settings.download_speed_limit_kb = Some(200);
+        downloader.update_settings(&settings)?;
+
+        assert!(!Arc::ptr_eq(
+            &bandwidth_limiter,
+            downloader.bandwidth_limiter.as_ref().unwrap()
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_forgets_hosts_without_connections() {
+        let downloader = ChapterDownloader::new(&Settings {
+            max_connections_per_host: Some(1),
+            ..Default::default()
         })
+        .unwrap();
+        let url = |host: &str| Url::parse(&format!("https://{host}/page.jpg")).unwrap();
+
+        let permit = downloader
+            .acquire_host_connection(&url("a.example.com"))
+            .await;
+        assert!(permit.is_some());
+        drop(
+            downloader
+                .acquire_host_connection(&url("b.example.com"))
+                .await,
+        );
+        drop(
+            downloader
+                .acquire_host_connection(&url("c.example.com"))
+                .await,
+        );
+
+        let mut hosts = downloader
+            .host_connection_limits
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        hosts.sort();
+        assert_eq!(hosts, vec!["a.example.com", "c.example.com"]);
+
+        drop(permit);
+    }
 }
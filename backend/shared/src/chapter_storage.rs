@@ -1,23 +1,63 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io::{self, Read};
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use base64::{engine::general_purpose, Engine as _};
-use log::debug;
+use log::{debug, warn};
 use sha2::{Digest, Sha256};
 use size::Size;
 use tempfile::NamedTempFile;
 use walkdir::{DirEntry, WalkDir};
 
-use crate::model::ChapterId;
+use crate::model::{ChapterId, SourceId};
 
 const CHAPTER_FILE_EXTENSION: &str = "cbz";
+const PARTIAL_DOWNLOADS_FOLDER_NAME: &str = ".partial";
+/// Partial downloads which weren't touched for this long are assumed to be abandoned.
+const PARTIAL_DOWNLOAD_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 
 #[derive(Clone)]
 pub struct ChapterStorage {
     downloads_folder_path: PathBuf,
+    previous_downloads_folder_path: Option<PathBuf>,
     storage_size_limit: Size,
+    legacy_path_fallback_enabled: bool,
+}
+
+/// The outcome of renaming chapters stored with the legacy path format.
+#[derive(Default, Debug)]
+pub struct LegacyChaptersMigration {
+    pub migrated: usize,
+    /// Legacy chapter files which didn't match any of the given chapters.
+    pub unmapped: Vec<PathBuf>,
+    /// Legacy chapter files which matched chapters from more than one manga. The legacy format
+    /// doesn't include the manga ID, so there's no way to tell which one the file belongs to.
+    pub ambiguous: Vec<PathBuf>,
+}
+
+/// The chapter files found in a folder that used to be the downloads folder.
+#[derive(Default, Debug)]
+pub struct ChapterFilesToImport {
+    pub chapter_files: Vec<PathBuf>,
+    /// `.cbz` files which aren't named like the chapters we download, and thus are probably not
+    /// ours to move.
+    pub unrecognized: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChapterFileImport {
+    Imported {
+        path: PathBuf,
+        size: Size,
+    },
+    /// A chapter with the same name is already stored, so the file was left in place.
+    AlreadyStored,
+    /// Importing the file would exceed the storage size limit, so it was left in place.
+    StorageFull,
 }
 
 impl ChapterStorage {
@@ -27,7 +67,9 @@ impl ChapterStorage {
 
         Ok(Self {
             downloads_folder_path,
+            previous_downloads_folder_path: None,
             storage_size_limit,
+            legacy_path_fallback_enabled: true,
         })
     }
 
@@ -37,6 +79,10 @@ impl ChapterStorage {
             return Some(new_path);
         }
 
+        if !self.legacy_path_fallback_enabled {
+            return None;
+        }
+
         // Backwards compatibility: check the old path format
         let old_path = self.path_for_chapter_legacy(id);
         if old_path.exists() {
@@ -57,7 +103,11 @@ impl ChapterStorage {
         id: &ChapterId,
         temporary_file: NamedTempFile,
     ) -> Result<PathBuf> {
-        let mut current_size = self.calculate_storage_size();
+        // The pages of the chapter being persisted are about to be removed from the partial
+        // downloads, so they shouldn't count twice.
+        let own_partial_download_size =
+            Size::from_bytes(folder_size(&self.get_partial_download_path(id)));
+        let mut current_size = self.calculate_storage_size() - own_partial_download_size;
         let persisted_chapter_size = Size::from_bytes(temporary_file.as_file().metadata()?.size());
 
         while current_size + persisted_chapter_size > self.storage_size_limit {
@@ -75,7 +125,7 @@ impl ChapterStorage {
                     persisted_chapter_size,
                 ))?;
 
-            current_size = self.calculate_storage_size();
+            current_size = self.calculate_storage_size() - own_partial_download_size;
         }
 
         // Persist using the new path format
@@ -85,22 +135,287 @@ impl ChapterStorage {
         Ok(path)
     }
 
+    /// Returns the folder where the pages of a chapter are kept while it is being downloaded, so
+    /// that an interrupted download can be resumed later.
+    pub fn get_partial_download_path(&self, id: &ChapterId) -> PathBuf {
+        let chapter_path = self.path_for_chapter(id);
+        let folder_name = chapter_path.file_stem().unwrap();
+
+        self.partial_downloads_folder_path().join(folder_name)
+    }
+
+    /// Removes the partial downloads which weren't resumed for a long time, so that they don't
+    /// take space forever.
+    pub fn remove_stale_partial_downloads(&self) -> Result<()> {
+        let partial_downloads_folder_path = self.partial_downloads_folder_path();
+        if !partial_downloads_folder_path.exists() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(&partial_downloads_folder_path)? {
+            let path = entry?.path();
+            let modified = fs::metadata(&path)?.modified()?;
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default();
+
+            if age > PARTIAL_DOWNLOAD_MAX_AGE {
+                debug!(
+                    "remove_stale_partial_downloads: removing {}",
+                    path.display()
+                );
+
+                fs::remove_dir_all(&path)
+                    .with_context(|| format!("while removing {}", path.display()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_partial_download(&self, id: &ChapterId) -> Result<()> {
+        let path = self.get_partial_download_path(id);
+
+        if path.exists() {
+            fs::remove_dir_all(&path).with_context(|| {
+                format!("while removing partial download at {}", path.display())
+            })?;
+        }
+
+        Ok(())
+    }
+
     pub fn set_downloads_folder_path(&mut self, path: PathBuf) -> Result<()> {
         fs::create_dir_all(&path)
             .with_context(|| "while trying to ensure chapter storage exists")?;
 
-        self.downloads_folder_path = path;
+        if path != self.downloads_folder_path {
+            self.previous_downloads_folder_path =
+                Some(std::mem::replace(&mut self.downloads_folder_path, path));
+            // The new folder might have never been migrated to the new path format.
+            self.legacy_path_fallback_enabled = true;
+        }
+
+        Ok(())
+    }
+
+    /// Stops looking for chapters stored with the legacy path format. Should only be called
+    /// after all legacy chapters were migrated with `migrate_legacy_chapters`.
+    pub fn disable_legacy_path_fallback(&mut self) {
+        self.legacy_path_fallback_enabled = false;
+    }
+
+    /// Renames chapters stored with the legacy path format to the current one.
+    ///
+    /// The legacy file names can't be mapped back into a `ChapterId`, so the candidates must be
+    /// passed in `chapter_ids` (e.g. all chapters we know about). Only the files named like a
+    /// legacy chapter of one of their sources are considered, so that other `.cbz` files the user
+    /// might keep in the downloads folder are left alone.
+    pub fn migrate_legacy_chapters(
+        &self,
+        chapter_ids: impl IntoIterator<Item = ChapterId>,
+    ) -> Result<LegacyChaptersMigration> {
+        let chapter_ids: Vec<_> = chapter_ids.into_iter().collect();
+        let source_ids: Vec<_> = chapter_ids
+            .iter()
+            .map(|chapter_id| chapter_id.source_id().clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        // Legacy chapters were always stored right inside the downloads folder.
+        let mut legacy_chapter_files: HashSet<PathBuf> =
+            chapter_files_in(WalkDir::new(&self.downloads_folder_path).max_depth(1))
+                .map(|entry| entry.into_path())
+                .filter(|path| is_legacy_chapter_file_name(path, &source_ids))
+                .collect();
+
+        let mut chapter_ids_by_legacy_path: HashMap<PathBuf, Vec<ChapterId>> = HashMap::new();
+        for chapter_id in chapter_ids {
+            let legacy_path = self.path_for_chapter_legacy(&chapter_id);
+
+            if legacy_chapter_files.remove(&legacy_path) {
+                chapter_ids_by_legacy_path.insert(legacy_path, vec![chapter_id]);
+            } else if let Some(chapter_ids) = chapter_ids_by_legacy_path.get_mut(&legacy_path) {
+                chapter_ids.push(chapter_id);
+            }
+        }
+
+        let mut migration = LegacyChaptersMigration {
+            unmapped: legacy_chapter_files.into_iter().collect(),
+            ..Default::default()
+        };
+
+        for (legacy_path, chapter_ids) in chapter_ids_by_legacy_path {
+            let [chapter_id] = chapter_ids.as_slice() else {
+                migration.ambiguous.push(legacy_path);
+
+                continue;
+            };
+
+            let new_path = self.path_for_chapter(chapter_id);
+            if new_path.exists() {
+                debug!(
+                    "migrate_legacy_chapters: {} was already downloaded again, removing {}",
+                    new_path.display(),
+                    legacy_path.display()
+                );
+
+                fs::remove_file(&legacy_path)?;
+            } else {
+                fs::rename(&legacy_path, &new_path).with_context(|| {
+                    format!(
+                        "while renaming {} to {}",
+                        legacy_path.display(),
+                        new_path.display()
+                    )
+                })?;
+            }
+
+            migration.migrated += 1;
+        }
+
+        Ok(migration)
+    }
+
+    pub fn downloads_folder_path(&self) -> &Path {
+        &self.downloads_folder_path
+    }
+
+    /// The downloads folder in use before the last call to `set_downloads_folder_path`, if it
+    /// was changed since the server started.
+    pub fn previous_downloads_folder_path(&self) -> Option<&Path> {
+        self.previous_downloads_folder_path.as_deref()
+    }
+
+    /// Lists the chapter files stored directly inside `folder`.
+    ///
+    /// Only files named like the chapters we download are considered: either by the current
+    /// path format, or by the legacy one (`<source id>-<chapter id>.cbz`) for one of the given
+    /// sources. Subfolders are never looked into, as `folder` might as well be some folder
+    /// where the user keeps their own comics.
+    pub fn find_chapter_files_to_import(
+        &self,
+        folder: &Path,
+        source_ids: &[SourceId],
+    ) -> Result<ChapterFilesToImport> {
+        let mut files_to_import = ChapterFilesToImport::default();
+
+        // The downloads folder might be `folder` itself.
+        if folder.canonicalize()? == self.downloads_folder_path.canonicalize()? {
+            return Ok(files_to_import);
+        }
+
+        for entry in chapter_files_in(WalkDir::new(folder).max_depth(1)) {
+            let path = entry.into_path();
+
+            if is_chapter_file_name(&path) || is_legacy_chapter_file_name(&path, source_ids) {
+                files_to_import.chapter_files.push(path);
+            } else {
+                files_to_import.unrecognized.push(path);
+            }
+        }
+
+        Ok(files_to_import)
+    }
+
+    /// Moves a chapter file from somewhere else into this storage, keeping its file name.
+    ///
+    /// `storage_size` is the current size of the storage (see `calculate_storage_size`). It's
+    /// kept by the caller, so that importing many files doesn't walk the whole storage for each
+    /// one of them.
+    ///
+    /// If the file cannot be renamed into place (e.g. the downloads folder lives on another
+    /// device, like an SD card), it gets copied instead, and the original file is only removed
+    /// after the copy is verified to have the same contents.
+    pub fn import_chapter_file(
+        &self,
+        path: &Path,
+        storage_size: Size,
+    ) -> Result<ChapterFileImport> {
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+        let target_path = self.downloads_folder_path.join(file_name);
+
+        if target_path.exists() {
+            warn!(
+                "import_chapter_file: {} already exists, leaving {} in place",
+                target_path.display(),
+                path.display()
+            );
+
+            return Ok(ChapterFileImport::AlreadyStored);
+        }
+
+        let file_size = Size::from_bytes(fs::metadata(path)?.size());
+        if storage_size + file_size > self.storage_size_limit {
+            warn!(
+                "import_chapter_file: {} doesn't fit in the storage size limit of {}, leaving it \
+                in place",
+                path.display(),
+                self.storage_size_limit
+            );
+
+            return Ok(ChapterFileImport::StorageFull);
+        }
+
+        if let Err(e) = fs::rename(path, &target_path) {
+            debug!(
+                "import_chapter_file: couldn't rename {} into {} ({e}), copying instead",
+                path.display(),
+                target_path.display()
+            );
+
+            self.copy_chapter_file(path, &target_path)?;
+            fs::remove_file(path)
+                .with_context(|| format!("while removing {} after copying it", path.display()))?;
+        }
+
+        Ok(ChapterFileImport::Imported {
+            path: target_path,
+            size: file_size,
+        })
+    }
+
+    fn copy_chapter_file(&self, source_path: &Path, target_path: &Path) -> Result<()> {
+        // Copy into a temporary file first, so that a failed copy never leaves a borked .cbz
+        // file in the chapter storage.
+        let mut temporary_file = NamedTempFile::new_in(&self.downloads_folder_path)?;
+        io::copy(
+            &mut fs::File::open(source_path)?,
+            temporary_file.as_file_mut(),
+        )
+        .with_context(|| format!("while copying {}", source_path.display()))?;
+        temporary_file.as_file().sync_all()?;
+
+        let source_hash = hash_file(source_path)?;
+        let copied_hash = hash_file(temporary_file.path())?;
+        if source_hash != copied_hash {
+            bail!(
+                "copy of {} doesn't match the original file",
+                source_path.display()
+            );
+        }
+
+        temporary_file.persist(target_path)?;
 
         Ok(())
     }
 
-    fn calculate_storage_size(&self) -> Size {
+    /// The size of all stored chapters, including the pages of partial downloads.
+    pub fn calculate_storage_size(&self) -> Size {
         let size_in_bytes: u64 = self
             .chapter_files_iterator()
             .filter_map(|entry| entry.metadata().ok().map(|metadata| metadata.size()))
             .sum();
 
-        Size::from_bytes(size_in_bytes)
+        Size::from_bytes(size_in_bytes + folder_size(&self.partial_downloads_folder_path()))
+    }
+
+    fn partial_downloads_folder_path(&self) -> PathBuf {
+        self.downloads_folder_path
+            .join(PARTIAL_DOWNLOADS_FOLDER_NAME)
     }
 
     fn evict_least_recently_modified_chapter(&self) -> Result<()> {
@@ -135,19 +450,7 @@ impl ChapterStorage {
     }
 
     fn chapter_files_iterator(&self) -> impl Iterator<Item = DirEntry> {
-        WalkDir::new(&self.downloads_folder_path)
-            .into_iter()
-            .filter_map(|entry| {
-                let entry = entry.ok()?;
-                let extension = entry.path().extension()?;
-                let metadata = entry.metadata().ok()?;
-
-                if !metadata.is_file() || extension != CHAPTER_FILE_EXTENSION {
-                    return None;
-                }
-
-                Some(entry)
-            })
+        chapter_files_in(WalkDir::new(&self.downloads_folder_path))
     }
 
     // DEPRECATED: This function provides backwards compatibility for the old chapter path format.
@@ -177,3 +480,242 @@ impl ChapterStorage {
         self.downloads_folder_path.join(output_filename)
     }
 }
+
+fn chapter_files_in(walk_dir: WalkDir) -> impl Iterator<Item = DirEntry> {
+    walk_dir.into_iter().filter_map(|entry| {
+        let entry = entry.ok()?;
+        let extension = entry.path().extension()?;
+        let metadata = entry.metadata().ok()?;
+
+        if !metadata.is_file() || extension != CHAPTER_FILE_EXTENSION {
+            return None;
+        }
+
+        Some(entry)
+    })
+}
+
+/// Whether `path` is named like the chapter files created by `path_for_chapter`.
+fn is_chapter_file_name(path: &Path) -> bool {
+    // A SHA-256 hash is 43 characters long when encoded as unpadded base64.
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| {
+            stem.len() == 43
+                && general_purpose::URL_SAFE_NO_PAD
+                    .decode(stem)
+                    .is_ok_and(|decoded| decoded.len() == 32)
+        })
+}
+
+/// Whether `path` is named like the chapter files created by `path_for_chapter_legacy`, for one
+/// of the given sources.
+fn is_legacy_chapter_file_name(path: &Path, source_ids: &[SourceId]) -> bool {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| {
+            source_ids.iter().any(|source_id| {
+                stem.strip_prefix(source_id.value().as_str())
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    .is_some_and(|chapter_id| !chapter_id.is_empty())
+            })
+        })
+}
+
+fn folder_size(folder: &Path) -> u64 {
+    WalkDir::new(folder)
+        .into_iter()
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.size())
+        .sum()
+}
+
+fn hash_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn it_only_imports_our_chapter_files_from_the_previous_folder() -> Result<()> {
+        let previous_folder = tempdir()?;
+        let downloads_folder = tempdir()?;
+        let chapter_storage =
+            ChapterStorage::new(downloads_folder.path().to_owned(), Size::from_megabytes(1))?;
+        let chapter_id = ChapterId::from_strings(
+            "en.source".to_string(),
+            "manga".to_string(),
+            "chapter".to_string(),
+        );
+
+        let hashed_file_name = chapter_storage
+            .path_for_chapter(&chapter_id)
+            .file_name()
+            .unwrap()
+            .to_owned();
+        fs::write(previous_folder.path().join(&hashed_file_name), b"hashed")?;
+        fs::write(previous_folder.path().join("en.source-1.cbz"), b"legacy")?;
+        fs::write(previous_folder.path().join("My Comic.cbz"), b"not ours")?;
+        fs::create_dir(previous_folder.path().join("nested"))?;
+        fs::write(
+            previous_folder
+                .path()
+                .join("nested")
+                .join(&hashed_file_name),
+            b"nested",
+        )?;
+
+        let files_to_import = chapter_storage.find_chapter_files_to_import(
+            previous_folder.path(),
+            &[SourceId::new("en.source".to_string())],
+        )?;
+
+        assert_eq!(files_to_import.chapter_files.len(), 2);
+        assert_eq!(
+            files_to_import.unrecognized,
+            vec![previous_folder.path().join("My Comic.cbz")]
+        );
+
+        let already_stored_path = previous_folder.path().join("en.source-1.cbz");
+        fs::write(downloads_folder.path().join("en.source-1.cbz"), b"legacy")?;
+
+        assert_eq!(
+            chapter_storage.import_chapter_file(&already_stored_path, Size::from_bytes(0))?,
+            ChapterFileImport::AlreadyStored
+        );
+        assert!(already_stored_path.exists());
+
+        let hashed_path = previous_folder.path().join(&hashed_file_name);
+
+        assert_eq!(
+            chapter_storage.import_chapter_file(&hashed_path, Size::from_bytes(0))?,
+            ChapterFileImport::Imported {
+                path: downloads_folder.path().join(&hashed_file_name),
+                size: Size::from_bytes(6),
+            }
+        );
+        assert!(!hashed_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_leaves_ambiguous_legacy_chapters_in_place() -> Result<()> {
+        let downloads_folder = tempdir()?;
+        let chapter_storage =
+            ChapterStorage::new(downloads_folder.path().to_owned(), Size::from_megabytes(1))?;
+        let chapter_id = |manga_id: &str, chapter_id: &str| {
+            ChapterId::from_strings(
+                "en.source".to_string(),
+                manga_id.to_string(),
+                chapter_id.to_string(),
+            )
+        };
+
+        let unique_chapter_id = chapter_id("manga-1", "1");
+        fs::write(downloads_folder.path().join("en.source-1.cbz"), b"unique")?;
+        fs::write(
+            downloads_folder.path().join("en.source-2.cbz"),
+            b"ambiguous",
+        )?;
+        fs::write(downloads_folder.path().join("en.source-3.cbz"), b"unmapped")?;
+        fs::write(downloads_folder.path().join("My Comic.cbz"), b"not ours")?;
+
+        let migration = chapter_storage.migrate_legacy_chapters([
+            unique_chapter_id.clone(),
+            chapter_id("manga-1", "2"),
+            chapter_id("manga-2", "2"),
+        ])?;
+
+        assert_eq!(migration.migrated, 1);
+        assert!(chapter_storage
+            .path_for_chapter(&unique_chapter_id)
+            .exists());
+        assert_eq!(
+            migration.ambiguous,
+            vec![downloads_folder.path().join("en.source-2.cbz")]
+        );
+        assert!(downloads_folder.path().join("en.source-2.cbz").exists());
+        assert_eq!(
+            migration.unmapped,
+            vec![downloads_folder.path().join("en.source-3.cbz")]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_migrates_legacy_chapters_moved_into_a_new_folder() -> Result<()> {
+        let previous_folder = tempdir()?;
+        let downloads_folder = tempdir()?;
+        let mut chapter_storage =
+            ChapterStorage::new(previous_folder.path().to_owned(), Size::from_megabytes(1))?;
+        let chapter_id = ChapterId::from_strings(
+            "en.source".to_string(),
+            "manga".to_string(),
+            "1".to_string(),
+        );
+
+        chapter_storage.set_downloads_folder_path(downloads_folder.path().to_owned())?;
+        let migration = chapter_storage.migrate_legacy_chapters([chapter_id.clone()])?;
+        assert_eq!(migration.migrated, 0);
+        assert!(migration.unmapped.is_empty() && migration.ambiguous.is_empty());
+        chapter_storage.disable_legacy_path_fallback();
+
+        let legacy_path = previous_folder.path().join("en.source-1.cbz");
+        fs::write(&legacy_path, b"legacy")?;
+        let files_to_import = chapter_storage.find_chapter_files_to_import(
+            previous_folder.path(),
+            &[SourceId::new("en.source".to_string())],
+        )?;
+        assert_eq!(files_to_import.chapter_files, vec![legacy_path.clone()]);
+        chapter_storage.import_chapter_file(&legacy_path, Size::from_bytes(0))?;
+
+        let migration = chapter_storage.migrate_legacy_chapters([chapter_id.clone()])?;
+
+        assert_eq!(migration.migrated, 1);
+        assert_eq!(
+            chapter_storage.get_stored_chapter(&chapter_id),
+            Some(chapter_storage.path_for_chapter(&chapter_id))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_does_not_import_chapters_over_the_storage_size_limit() -> Result<()> {
+        let previous_folder = tempdir()?;
+        let downloads_folder = tempdir()?;
+        let chapter_storage =
+            ChapterStorage::new(downloads_folder.path().to_owned(), Size::from_bytes(4))?;
+
+        let path = previous_folder.path().join("en.source-1.cbz");
+        fs::write(&path, b"too large")?;
+
+        assert_eq!(
+            chapter_storage.import_chapter_file(&path, chapter_storage.calculate_storage_size())?,
+            ChapterFileImport::StorageFull
+        );
+        assert!(path.exists());
+
+        Ok(())
+    }
+}
@@ -175,6 +175,20 @@ impl Database {
         rows.into_iter().map(|row| row.into()).collect()
     }
 
+    pub async fn find_all_cached_chapter_ids(&self) -> Vec<ChapterId> {
+        let rows = sqlx::query_as!(
+            ChapterIdRow,
+            r#"
+                SELECT source_id, manga_id, chapter_id FROM chapter_informations;
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap();
+
+        rows.into_iter().map(|row| row.chapter_id()).collect()
+    }
+
     pub async fn upsert_cached_manga_information(&self, manga_information: MangaInformation) {
         let source_id = manga_information.id.source_id().value();
         let manga_id = manga_information.id.value();
@@ -432,6 +446,19 @@ impl From<ChapterInformationsRow> for ChapterInformation {
     }
 }
 
+#[derive(sqlx::FromRow)]
+struct ChapterIdRow {
+    source_id: String,
+    manga_id: String,
+    chapter_id: String,
+}
+
+impl ChapterIdRow {
+    pub fn chapter_id(self) -> ChapterId {
+        ChapterId::from_strings(self.source_id, self.manga_id, self.chapter_id)
+    }
+}
+
 #[derive(sqlx::FromRow)]
 struct MangaLibraryRow {
     source_id: String,
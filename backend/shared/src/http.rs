@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::{Client, ClientBuilder, Method, NoProxy, Proxy, StatusCode};
+use url::Url;
+
+use crate::settings::NetworkSettings;
+
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Returned when a request doesn't complete within the configured timeouts.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("request to {url} timed out")]
+pub struct RequestTimeoutError {
+    pub url: Url,
+}
+
+/// Describes which requests are retried after failing, and how long to wait between attempts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_settings(settings: &NetworkSettings) -> Self {
+        Self {
+            max_retries: settings.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            initial_backoff: settings
+                .retry_backoff_milliseconds
+                .map_or(DEFAULT_RETRY_BACKOFF, Duration::from_millis),
+        }
+    }
+
+    /// Only idempotent requests are retried, as retrying other requests might repeat their
+    /// side effects.
+    pub fn allows_retrying(&self, method: &Method) -> bool {
+        self.max_retries > 0
+            && matches!(
+                *method,
+                Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS
+            )
+    }
+
+    pub fn should_retry_error(&self, error: &reqwest::Error) -> bool {
+        error.is_timeout() || error.is_connect() || error.is_body()
+    }
+
+    pub fn should_retry_status(&self, status: StatusCode) -> bool {
+        status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS
+    }
+
+    /// How long to wait before the given retry (starting at 1).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+    }
+}
+
+/// Builds a HTTP client configured according to the network settings.
+///
+/// The client keeps a pool of connections, so it should be reused across requests instead of
+/// building a new one every time.
+pub fn build_client(settings: &NetworkSettings) -> Result<Client> {
+    client_builder(settings)?
+        .build()
+        .context("couldn't build HTTP client")
+}
+
+/// Returns a client builder configured according to the network settings, for when the client
+/// needs further customization before being built.
+pub fn client_builder(settings: &NetworkSettings) -> Result<ClientBuilder> {
+    let timeout = settings
+        .timeout_seconds
+        .map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+
+    Ok(download_client_builder(settings)?.timeout(timeout))
+}
+
+/// Returns a client builder for downloading chapter pages. It shares the connection timeout and
+/// proxy configuration with [`client_builder`], but has no timeout for the whole request, as
+/// large pages on slow connections can legitimately take longer than any fixed limit.
+pub fn download_client_builder(settings: &NetworkSettings) -> Result<ClientBuilder> {
+    let connect_timeout = settings
+        .connect_timeout_seconds
+        .map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from_secs);
+
+    let mut builder = Client::builder().connect_timeout(connect_timeout);
+
+    if let Some(proxy_url) = &settings.proxy {
+        let mut proxy = Proxy::all(proxy_url.clone())
+            .with_context(|| format!("invalid proxy URL: {proxy_url}"))?;
+
+        if let Some(username) = &settings.proxy_username {
+            proxy = proxy.basic_auth(
+                username,
+                settings.proxy_password.as_deref().unwrap_or_default(),
+            );
+        }
+
+        if !settings.proxy_bypass.is_empty() {
+            proxy = proxy.no_proxy(NoProxy::from_string(&settings.proxy_bypass.join(",")));
+        }
+
+        builder = builder.proxy(proxy);
+    }
+
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_doubles_the_backoff_after_every_retry() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+        };
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(3), Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn it_only_retries_idempotent_requests() {
+        let policy = RetryPolicy::from_settings(&NetworkSettings::default());
+
+        assert!(policy.allows_retrying(&Method::GET));
+        assert!(!policy.allows_retrying(&Method::POST));
+
+        let disabled = RetryPolicy {
+            max_retries: 0,
+            ..policy
+        };
+        assert!(!disabled.allows_retrying(&Method::GET));
+    }
+}
@@ -0,0 +1,214 @@
+use std::io::Cursor;
+
+use anyhow::{Context, Result};
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, GenericImageView, ImageFormat,
+};
+
+use crate::settings::{ImageOutputFormat, ImageProcessingSettings, ScreenResolution};
+
+const JPEG_QUALITY: u8 = 85;
+
+/// How much taller than the screen (proportionally) a page must be before it gets split. Avoids
+/// splitting pages which are just slightly taller than the screen into a full page and a sliver.
+const SPLIT_THRESHOLD: f64 = 1.5;
+
+/// A page (or a piece of it, if it was split) after being processed.
+pub struct ProcessedPage {
+    pub contents: Vec<u8>,
+    pub extension: &'static str,
+}
+
+/// Whether we're able to decode (and thus process) an encoded page. Only the formats enabled in
+/// the `image` crate's features can be decoded: notably, decoding AVIF would need native
+/// libraries.
+pub fn can_process(contents: &[u8]) -> bool {
+    matches!(
+        image::guess_format(contents),
+        Ok(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP)
+    )
+}
+
+/// Applies the processing configured in `settings` to an encoded page, returning the pieces it
+/// was split into (or a single one, if it wasn't split).
+pub fn process_page(
+    settings: &ImageProcessingSettings,
+    contents: &[u8],
+) -> Result<Vec<ProcessedPage>> {
+    let format = image::guess_format(contents).context("couldn't detect the page's format")?;
+    let mut image =
+        image::load_from_memory_with_format(contents, format).context("couldn't decode page")?;
+
+    if settings.grayscale {
+        image = image.grayscale();
+    }
+
+    let mut pieces = match settings.screen_resolution {
+        Some(screen_resolution) if settings.split_tall_pages => {
+            split_tall_page(image, screen_resolution)
+        }
+        _ => vec![image],
+    };
+
+    if let (Some(screen_resolution), true) = (settings.screen_resolution, settings.downscale) {
+        pieces = pieces
+            .into_iter()
+            .map(|piece| downscale(piece, screen_resolution))
+            .collect();
+    }
+
+    let output_format = settings.output_format.unwrap_or(match format {
+        ImageFormat::Jpeg => ImageOutputFormat::Jpeg,
+        // We can't write everything we can read (e.g. WebP), so fall back to a lossless format.
+        _ => ImageOutputFormat::Png,
+    });
+
+    pieces
+        .iter()
+        .map(|piece| encode(piece, output_format))
+        .collect()
+}
+
+fn split_tall_page(image: DynamicImage, screen_resolution: ScreenResolution) -> Vec<DynamicImage> {
+    let (width, height) = image.dimensions();
+    let piece_height = (width as f64 * screen_resolution.height as f64
+        / screen_resolution.width as f64)
+        .round() as u32;
+
+    if piece_height == 0 || (height as f64) < piece_height as f64 * SPLIT_THRESHOLD {
+        return vec![image];
+    }
+
+    (0..height)
+        .step_by(piece_height as usize)
+        .map(|y| image.crop_imm(0, y, width, piece_height.min(height - y)))
+        .collect()
+}
+
+fn downscale(image: DynamicImage, screen_resolution: ScreenResolution) -> DynamicImage {
+    let (width, height) = image.dimensions();
+
+    if width <= screen_resolution.width && height <= screen_resolution.height {
+        return image;
+    }
+
+    // `resize` keeps the aspect ratio, fitting the image inside the given bounds.
+    image.resize(
+        screen_resolution.width,
+        screen_resolution.height,
+        FilterType::Triangle,
+    )
+}
+
+fn encode(image: &DynamicImage, format: ImageOutputFormat) -> Result<ProcessedPage> {
+    let mut contents = Cursor::new(Vec::new());
+
+    let extension = match format {
+        ImageOutputFormat::Jpeg => {
+            // JPEG doesn't support transparency, so drop the alpha channel if there's one.
+            let image = if image.color().has_color() {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            } else {
+                DynamicImage::ImageLuma8(image.to_luma8())
+            };
+
+            image.write_with_encoder(JpegEncoder::new_with_quality(&mut contents, JPEG_QUALITY))?;
+
+            "jpg"
+        }
+        ImageOutputFormat::Png => {
+            image.write_to(&mut contents, ImageFormat::Png)?;
+
+            "png"
+        }
+    };
+
+    Ok(ProcessedPage {
+        contents: contents.into_inner(),
+        extension,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage};
+
+    use super::*;
+
+    fn encoded_page(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_pixel(width, height, Rgb([255, 0, 0]));
+        let mut contents = Cursor::new(Vec::new());
+        image.write_to(&mut contents, ImageFormat::Png).unwrap();
+
+        contents.into_inner()
+    }
+
+    fn decoded_pieces(pieces: &[ProcessedPage]) -> Vec<DynamicImage> {
+        pieces
+            .iter()
+            .map(|piece| image::load_from_memory(&piece.contents).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_converts_pages_to_grayscale() -> Result<()> {
+        let settings = ImageProcessingSettings {
+            grayscale: true,
+            ..Default::default()
+        };
+
+        let pieces = process_page(&settings, &encoded_page(4, 4))?;
+
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(pieces[0].extension, "png");
+        assert!(!decoded_pieces(&pieces)[0].color().has_color());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_splits_tall_pages_into_screen_sized_pieces() -> Result<()> {
+        let settings = ImageProcessingSettings {
+            screen_resolution: Some(ScreenResolution {
+                width: 10,
+                height: 20,
+            }),
+            split_tall_pages: true,
+            ..Default::default()
+        };
+
+        let pieces = decoded_pieces(&process_page(&settings, &encoded_page(10, 50))?);
+
+        let dimensions: Vec<_> = pieces.iter().map(|piece| piece.dimensions()).collect();
+        assert_eq!(dimensions, vec![(10, 20), (10, 20), (10, 10)]);
+
+        // Pages only slightly taller than the screen are kept whole.
+        let pieces = process_page(&settings, &encoded_page(10, 25))?;
+        assert_eq!(pieces.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_downscales_pages_larger_than_the_screen() -> Result<()> {
+        let settings = ImageProcessingSettings {
+            screen_resolution: Some(ScreenResolution {
+                width: 10,
+                height: 20,
+            }),
+            downscale: true,
+            output_format: Some(ImageOutputFormat::Jpeg),
+            ..Default::default()
+        };
+
+        let pieces = process_page(&settings, &encoded_page(40, 40))?;
+
+        assert_eq!(pieces[0].extension, "jpg");
+        assert_eq!(decoded_pieces(&pieces)[0].dimensions(), (10, 10));
+
+        let pieces = process_page(&settings, &encoded_page(5, 5))?;
+        assert_eq!(decoded_pieces(&pieces)[0].dimensions(), (5, 5));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
+pub mod bandwidth_limiter;
 pub mod chapter_downloader;
 pub mod chapter_storage;
 pub mod database;
+pub mod http;
+pub mod image_processing;
 pub mod model;
 pub mod settings;
 pub mod source;
@@ -1,8 +1,19 @@
-use std::{fs::File, path::Path};
+use std::{collections::HashMap, fs::File, path::Path};
 
 use anyhow::{Context, Result};
 
-use super::schema::Settings;
+use super::schema::{ImageProcessingSettings, Settings, SourceLimitsSettings};
+
+const DEFAULT_CONCURRENT_REQUESTS: usize = 4;
+const DEFAULT_CONCURRENT_SEARCH_REQUESTS: usize = 5;
+const DEFAULT_USER_AGENT: &str =
+    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:107.0) Gecko/20100101 Firefox/107.0";
+const DEFAULT_SEARCH_FUEL: u64 = 1_000_000_000;
+const DEFAULT_CHAPTER_LIST_FUEL: u64 = 2_000_000_000;
+const DEFAULT_PAGE_LIST_FUEL: u64 = 1_000_000_000;
+const DEFAULT_IMAGE_REQUEST_FUEL: u64 = 100_000_000;
+const DEFAULT_SOURCE_MAX_MEMORY_MB: usize = 64;
+const DEFAULT_SOURCE_MAX_INSTANCES: usize = 2;
 
 impl Settings {
     pub fn from_file(path: &Path) -> Result<Self> {
@@ -18,4 +29,69 @@ impl Settings {
 
         Ok(serde_json_lenient::to_writer_pretty(file, self)?)
     }
+
+    pub fn chapter_download_concurrency(&self) -> usize {
+        self.concurrent_requests
+            .unwrap_or(DEFAULT_CONCURRENT_REQUESTS)
+            .max(1)
+    }
+
+    pub fn search_concurrency(&self) -> usize {
+        self.concurrent_search_requests
+            .unwrap_or(DEFAULT_CONCURRENT_SEARCH_REQUESTS)
+            .max(1)
+    }
+
+    /// Returns the headers every request made by the given source starts with.
+    pub fn default_request_headers(&self, source_id: &str) -> HashMap<String, String> {
+        let source_network_settings = self.source_network_settings.get(source_id);
+        let user_agent = source_network_settings
+            .and_then(|settings| settings.user_agent.as_deref())
+            .or(self.network.user_agent.as_deref())
+            .unwrap_or(DEFAULT_USER_AGENT);
+
+        let mut headers = HashMap::from([("User-Agent".to_string(), user_agent.to_string())]);
+        if let Some(source_network_settings) = source_network_settings {
+            headers.extend(source_network_settings.headers.clone());
+        }
+
+        headers
+    }
+}
+
+impl ImageProcessingSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.grayscale || self.downscale || self.split_tall_pages || self.output_format.is_some()
+    }
+}
+
+impl SourceLimitsSettings {
+    pub fn search_fuel(&self) -> u64 {
+        self.search_fuel.unwrap_or(DEFAULT_SEARCH_FUEL)
+    }
+
+    pub fn chapter_list_fuel(&self) -> u64 {
+        self.chapter_list_fuel.unwrap_or(DEFAULT_CHAPTER_LIST_FUEL)
+    }
+
+    pub fn page_list_fuel(&self) -> u64 {
+        self.page_list_fuel.unwrap_or(DEFAULT_PAGE_LIST_FUEL)
+    }
+
+    pub fn image_request_fuel(&self) -> u64 {
+        self.image_request_fuel
+            .unwrap_or(DEFAULT_IMAGE_REQUEST_FUEL)
+    }
+
+    pub fn max_memory_bytes(&self) -> usize {
+        self.max_memory_mb
+            .unwrap_or(DEFAULT_SOURCE_MAX_MEMORY_MB)
+            .saturating_mul(1024 * 1024)
+    }
+
+    pub fn max_instances(&self) -> usize {
+        self.max_instances
+            .unwrap_or(DEFAULT_SOURCE_MAX_INSTANCES)
+            .max(1)
+    }
 }
@@ -1,4 +1,8 @@
 mod implementation;
 mod schema;
 
-pub use schema::{ChapterSortingMode, Settings, SourceSettingValue, StorageSizeLimit};
+pub use schema::{
+    ChapterSortingMode, ConnectivityCheckSettings, ImageOutputFormat, ImageProcessingSettings,
+    NetworkSettings, ScreenResolution, Settings, SourceLimitsSettings, SourceNetworkSettings,
+    SourceSettingValue, StorageSizeLimit,
+};
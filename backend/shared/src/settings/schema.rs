@@ -27,6 +27,168 @@ pub enum ChapterSortingMode {
     ChapterDescending,
 }
 
+/// The format pages are re-encoded to when processing them.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageOutputFormat {
+    Jpeg,
+    Png,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ScreenResolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Processing applied to the chapter pages when downloading them. Pages are stored as they
+/// were served by the source if nothing is enabled.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ImageProcessingSettings {
+    /// Converts the pages to grayscale.
+    #[serde(default)]
+    pub grayscale: bool,
+
+    /// The resolution of the device's screen, used when downscaling or splitting pages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen_resolution: Option<ScreenResolution>,
+
+    /// Downscales pages bigger than `screen_resolution` to fit inside it.
+    #[serde(default)]
+    pub downscale: bool,
+
+    /// Splits pages much taller than `screen_resolution` (e.g. webtoon strips) into multiple
+    /// pages with the same aspect ratio as the screen.
+    #[serde(default)]
+    pub split_tall_pages: bool,
+
+    /// If set, pages are re-encoded to this format. Otherwise, processed pages keep their
+    /// original format when possible.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<ImageOutputFormat>,
+}
+
+/// Network-related settings for a specific source.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceNetworkSettings {
+    /// Accepts invalid TLS certificates when downloading images from this source. Only enable
+    /// this for sources known to serve images with broken certificates, as it allows the
+    /// traffic to be intercepted.
+    #[serde(default)]
+    pub accept_invalid_certificates: bool,
+
+    /// Overrides the User-Agent sent with this source's requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// Extra headers sent with all of this source's requests (e.g. `Referer`). The source can
+    /// still override them for specific requests.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+}
+
+/// Settings for the connectivity check done before sending source requests.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ConnectivityCheckSettings {
+    /// Skips the connectivity check entirely, always attempting to send requests.
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// Addresses, in the `host:port` format, to connect to when checking for a connection.
+    /// The check succeeds if any of them accepts the connection, so a local address (e.g. the
+    /// router's) can be used on networks blocking the default ones. Defaults to Cloudflare's
+    /// DNS servers (`1.1.1.1:80` and `1.0.0.1:80`), or to the proxy if one is configured.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<String>,
+
+    /// For how long the result of a check is reused, in seconds. Defaults to 10.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_seconds: Option<u64>,
+}
+
+/// Settings for the HTTP requests made by sources.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NetworkSettings {
+    /// Overrides the User-Agent sent with source requests. Can also be overridden for specific
+    /// sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// How long to wait for a connection to be established, in seconds. Defaults to 15.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_seconds: Option<u64>,
+
+    /// How long to wait for a request to complete (including reading the response), in
+    /// seconds. Defaults to 60. Does not apply to chapter page downloads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+
+    /// How many times idempotent requests (`GET`, `HEAD`, `PUT`, `DELETE` and `OPTIONS`) are
+    /// retried after timing out, failing to connect or receiving a server error. Defaults to 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_retries: Option<u32>,
+
+    /// How long to wait before retrying a request for the first time, in milliseconds. The
+    /// delay doubles after every retry. Defaults to 500.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_backoff_milliseconds: Option<u64>,
+
+    /// If set, all requests are sent through this proxy. HTTP, HTTPS and SOCKS5 proxies are
+    /// supported (e.g. `http://localhost:8080` or `socks5://localhost:1080`). Use `socks5h://`
+    /// to resolve host names through the proxy as well.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<Url>,
+
+    /// The username used to authenticate with the proxy, if it requires authentication.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_username: Option<String>,
+
+    /// The password used to authenticate with the proxy.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy_password: Option<String>,
+
+    /// Hosts that are reached directly instead of through the proxy. Entries can be domains
+    /// (which also match their subdomains), IP addresses or IP ranges (e.g. `192.168.0.0/16`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_bypass: Vec<String>,
+
+    /// Configures how rakuyomi checks for an internet connection before sending requests.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub connectivity_check: ConnectivityCheckSettings,
+}
+
+/// Limits on how much work a source can do, so that a misbehaving source can't hang or use up
+/// the memory of the device. Fuel is roughly the number of WASM instructions a source can
+/// execute in a single operation.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceLimitsSettings {
+    /// Fuel available when listing or searching mangas. Defaults to 1000000000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_fuel: Option<u64>,
+
+    /// Fuel available when listing the chapters of a manga. Defaults to 2000000000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapter_list_fuel: Option<u64>,
+
+    /// Fuel available when listing the pages of a chapter. Defaults to 1000000000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_list_fuel: Option<u64>,
+
+    /// Fuel available when building the request for a page image. Defaults to 100000000.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_request_fuel: Option<u64>,
+
+    /// The maximum amount of memory (in MB) a source can allocate. Defaults to 64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory_mb: Option<usize>,
+
+    /// How many operations (e.g. searches or image requests) can run on the same source at the
+    /// same time. Each one needs a separate instance of the source, using up to `max_memory_mb`
+    /// of memory. Defaults to 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_instances: Option<usize>,
+}
+
 /// Settings used to configure rakuyomi's behavior.
 #[derive(Serialize, Deserialize, Default, Clone, Debug, JsonSchema)]
 pub struct Settings {
@@ -56,10 +218,48 @@ pub struct Settings {
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub source_settings: HashMap<String, HashMap<String, SourceSettingValue>>,
 
+    /// Settings for the HTTP requests made by sources.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub network: NetworkSettings,
+
+    /// Source-specific network settings, keyed by the source ID.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub source_network_settings: HashMap<String, SourceNetworkSettings>,
+
+    /// Limits on the work done by sources.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub source_limits: SourceLimitsSettings,
+
     /// The order in which chapters will be displayed in the chapter listing. Defaults to
     /// `volume_descending`.
     #[serde(default)]
     pub chapter_sorting_mode: ChapterSortingMode,
+
+    /// Processing applied to the chapter pages when downloading them.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub image_processing: ImageProcessingSettings,
+
+    /// How many pages of a chapter are downloaded at the same time. Defaults to 4.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrent_requests: Option<usize>,
+
+    /// How many sources are searched at the same time. Defaults to 5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrent_search_requests: Option<usize>,
+
+    /// If set, limits how many connections to the same host can be open at the same time when
+    /// downloading chapters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_connections_per_host: Option<usize>,
+
+    /// If set, limits the download speed (in KB/s) of chapter downloads. The limit is shared
+    /// between all downloads happening at the same time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_speed_limit_kb: Option<u64>,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
 }
 
 fn default_storage_size_limit() -> StorageSizeLimit {
@@ -0,0 +1,99 @@
+use std::{borrow::Cow, sync::OnceLock};
+
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use regex::bytes::Regex;
+
+/// How many bytes at the start of a HTML document are searched for a `<meta>` declaring its
+/// charset. Matches what browsers do.
+const META_PRESCAN_LENGTH: usize = 1024;
+
+/// Decodes a HTML document into a string.
+///
+/// The encoding is taken from, in order: the byte order mark, the `charset` parameter of the
+/// `Content-Type` header and a `<meta>` tag near the start of the document. If none of them
+/// are present, the document is assumed to be UTF-8.
+pub fn decode_html<'a>(body: &'a [u8], content_type: Option<&str>) -> Cow<'a, str> {
+    let encoding = content_type
+        .and_then(encoding_from_content_type)
+        .or_else(|| encoding_from_meta(body))
+        .unwrap_or(UTF_8);
+
+    // `decode` gives precedence to the BOM, if there's one.
+    let (decoded, _, _) = encoding.decode(body);
+
+    decoded
+}
+
+/// Decodes a non-HTML text response (e.g. JSON) into a string, using the `charset` parameter
+/// of the `Content-Type` header if present, and UTF-8 otherwise.
+pub fn decode_text<'a>(body: &'a [u8], content_type: Option<&str>) -> Cow<'a, str> {
+    let encoding = content_type
+        .and_then(encoding_from_content_type)
+        .unwrap_or(UTF_8);
+    let (decoded, _, _) = encoding.decode(body);
+
+    decoded
+}
+
+fn encoding_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))
+}
+
+fn encoding_from_meta(body: &[u8]) -> Option<&'static Encoding> {
+    static META_CHARSET_REGEX: OnceLock<Regex> = OnceLock::new();
+
+    // Covers both `<meta charset="...">` and
+    // `<meta http-equiv="Content-Type" content="text/html; charset=...">`.
+    let regex = META_CHARSET_REGEX.get_or_init(|| {
+        Regex::new(r#"(?i-u)<meta[^>]+charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#).unwrap()
+    });
+    let prefix = &body[..body.len().min(META_PRESCAN_LENGTH)];
+    let label = regex.captures(prefix)?.get(1)?.as_bytes();
+    let encoding = Encoding::for_label(label)?;
+
+    // A document can't declare itself as UTF-16 from inside, as the declaration itself
+    // wouldn't be readable; browsers treat those as UTF-8 instead.
+    Some(match encoding.name() {
+        "UTF-16LE" | "UTF-16BE" => UTF_8,
+        "x-user-defined" => WINDOWS_1252,
+        _ => encoding,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_uses_the_content_type_charset() {
+        let (body, _, _) = encoding_rs::SHIFT_JIS.encode("日本語");
+
+        assert_eq!(
+            decode_html(&body, Some("text/html; charset=Shift_JIS")),
+            "日本語"
+        );
+        assert_eq!(
+            decode_text(&body, Some("application/json; charset=\"shift_jis\"")),
+            "日本語"
+        );
+    }
+
+    #[test]
+    fn it_sniffs_the_charset_from_meta_tags() {
+        let (encoded, _, _) = encoding_rs::EUC_KR.encode("한국어");
+        let mut body = br#"<html><head><meta http-equiv="Content-Type" content="text/html; charset=euc-kr"></head><body>"#.to_vec();
+        body.extend_from_slice(&encoded);
+
+        assert!(decode_html(&body, Some("text/html")).ends_with("<body>한국어"));
+    }
+
+    #[test]
+    fn it_defaults_to_utf8() {
+        assert_eq!(decode_html("olá".as_bytes(), None), "olá");
+    }
+}
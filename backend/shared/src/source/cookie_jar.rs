@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use anyhow::{Context, Result};
+use cookie_store::{CookieStore, RawCookie};
+use log::warn;
+use reqwest::header::HeaderValue;
+use tempfile::NamedTempFile;
+use url::Url;
+
+const COOKIES_FILE_EXTENSION: &str = "cookies.json";
+
+#[derive(Debug, Clone)]
+pub struct StoredCookie {
+    pub domain: String,
+    pub path: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// Cookies received by a source, shared between its `net` requests and image requests.
+///
+/// Whenever a response sets new cookies, the persistent ones are written to disk, so they
+/// survive restarts of the server (and reloads of the source).
+#[derive(Debug, Default)]
+pub struct SourceCookieJar {
+    path: Option<PathBuf>,
+    store: RwLock<CookieStore>,
+    /// Held while saving, so that concurrent responses (e.g. from different instances of the
+    /// source) write the jar one at a time, each with all the cookies received so far.
+    save_lock: Mutex<()>,
+}
+
+impl SourceCookieJar {
+    /// Returns the path where the cookies of the source installed at `source_path` are stored.
+    pub fn path_for_source(source_path: &Path) -> PathBuf {
+        source_path.with_extension(COOKIES_FILE_EXTENSION)
+    }
+
+    /// Loads the cookie jar stored at `path`, or an empty one if there's nothing stored yet.
+    ///
+    /// A jar that can't be parsed is moved aside (so it can still be inspected) and replaced by an
+    /// empty one, instead of keeping the source from loading.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let store = match fs::File::open(&path) {
+            Ok(file) => match CookieStore::load_json(BufReader::new(file)) {
+                Ok(store) => store,
+                Err(e) => {
+                    let mut corrupt_path = path.clone().into_os_string();
+                    corrupt_path.push(".corrupt");
+                    let corrupt_path = PathBuf::from(corrupt_path);
+                    warn!(
+                        "couldn't parse cookies at {}, moving them to {}: {e}",
+                        path.display(),
+                        corrupt_path.display()
+                    );
+                    fs::rename(&path, &corrupt_path).with_context(|| {
+                        format!("couldn't move corrupt cookies at {}", path.display())
+                    })?;
+
+                    CookieStore::default()
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CookieStore::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("couldn't open cookies at {}", path.display()))
+            }
+        };
+
+        Ok(Self {
+            path: Some(path),
+            store: RwLock::new(store),
+            save_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn cookies(&self) -> Vec<StoredCookie> {
+        let store = self.store.read().unwrap_or_else(|e| e.into_inner());
+
+        store
+            .iter_unexpired()
+            .map(|cookie| StoredCookie {
+                domain: String::from(&cookie.domain),
+                path: String::from(&cookie.path),
+                name: cookie.name().to_owned(),
+                value: cookie.value().to_owned(),
+            })
+            .collect()
+    }
+
+    /// Returns the value of the `Cookie` header that should be sent with a request to `url`.
+    pub fn header_value_for(&self, url: &Url) -> Option<String> {
+        let store = self.store.read().unwrap_or_else(|e| e.into_inner());
+        let header_value = store
+            .get_request_values(url)
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        (!header_value.is_empty()).then_some(header_value)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.store
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let _save_guard = self.save_lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Write to a temporary file first, so that we never leave a half-written jar behind.
+        let temporary_file = NamedTempFile::new_in(path.parent().unwrap_or(Path::new(".")))
+            .context("couldn't create a temporary file for the cookies")?;
+        let mut writer = BufWriter::new(temporary_file);
+
+        self.store
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .save_json(&mut writer)
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("couldn't serialize cookies")?;
+
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .persist(path)
+            .with_context(|| format!("couldn't write cookies to {}", path.display()))?;
+
+        Ok(())
+    }
+}
+
+impl reqwest::cookie::CookieStore for SourceCookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers
+            .filter_map(|header| header.to_str().ok())
+            .filter_map(|header| RawCookie::parse(header.to_owned()).ok())
+            .collect::<Vec<_>>();
+
+        if cookies.is_empty() {
+            return;
+        }
+
+        self.store
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .store_response_cookies(cookies.into_iter(), url);
+
+        let _ = self
+            .save()
+            .inspect_err(|e| warn!("couldn't persist source cookies: {e:?}"));
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        self.header_value_for(url)
+            .and_then(|value| HeaderValue::from_str(&value).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::cookie::CookieStore as _;
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn it_persists_cookies_across_loads() -> Result<()> {
+        let folder = tempdir()?;
+        let path = folder.path().join("source.cookies.json");
+        let url = Url::parse("https://example.com/chapter").unwrap();
+
+        let cookie_jar = SourceCookieJar::load(path.clone())?;
+        let persistent = HeaderValue::from_static("session=abc; Max-Age=3600");
+        let session_only = HeaderValue::from_static("temporary=xyz");
+        cookie_jar.set_cookies(&mut [&persistent, &session_only].into_iter(), &url);
+
+        let reloaded_cookie_jar = SourceCookieJar::load(path)?;
+
+        assert_eq!(
+            reloaded_cookie_jar.header_value_for(&url).as_deref(),
+            Some("session=abc")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_moves_corrupt_cookies_aside() -> Result<()> {
+        let folder = tempdir()?;
+        let path = folder.path().join("source.cookies.json");
+        fs::write(&path, "{ not json")?;
+
+        let cookie_jar = SourceCookieJar::load(path.clone())?;
+
+        assert!(cookie_jar.cookies().is_empty());
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(folder.path().join("source.cookies.json.corrupt"))?,
+            "{ not json"
+        );
+
+        Ok(())
+    }
+}
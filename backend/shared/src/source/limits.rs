@@ -0,0 +1,35 @@
+use wasmi::core::TrapCode;
+
+/// The kind of resource a source ran out of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExhaustedResource {
+    Fuel,
+    Memory,
+}
+
+impl std::fmt::Display for ExhaustedResource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fuel => write!(f, "fuel"),
+            Self::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+/// Raised when a source exceeds one of the limits set in the `source_limits` settings.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("source {source_id} ran out of {resource} while running {function_name}")]
+pub struct ResourceExhaustedError {
+    pub source_id: String,
+    pub function_name: &'static str,
+    pub resource: ExhaustedResource,
+}
+
+/// Returns which resource was exhausted, if `error` was caused by the source hitting its limits.
+pub(super) fn exhausted_resource(error: &wasmi::Error) -> Option<ExhaustedResource> {
+    match error.as_trap_code()? {
+        TrapCode::OutOfFuel => Some(ExhaustedResource::Fuel),
+        TrapCode::GrowthOperationLimited => Some(ExhaustedResource::Memory),
+        _ => None,
+    }
+}
@@ -1,29 +1,31 @@
 use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, warn};
 use reqwest::{Method, Request};
 use serde::Deserialize;
 use std::{
     fs,
+    ops::{Deref, DerefMut},
     path::Path,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, PoisonError, RwLock},
 };
+use tokio::sync::Semaphore;
 use tokio_util::sync::CancellationToken;
 use url::Url;
+use wasm_shared::ImportError;
 use wasmi::*;
 use zip::ZipArchive;
 
-use crate::settings::Settings;
+use crate::{settings::Settings, util::ConnectivityChecker};
 
 use self::{
+    cookie_jar::SourceCookieJar,
+    limits::ResourceExhaustedError,
     model::{Chapter, Filter, Manga, MangaPageResult, Page, SettingDefinition},
     source_settings::SourceSettings,
     wasm_imports::{
-        aidoku::register_aidoku_imports,
-        defaults::register_defaults_imports,
-        env::register_env_imports,
-        html::register_html_imports,
-        json::register_json_imports,
-        net::{register_net_imports, DEFAULT_USER_AGENT},
-        std::register_std_imports,
+        aidoku::register_aidoku_imports, defaults::register_defaults_imports,
+        env::register_env_imports, html::register_html_imports, json::register_json_imports,
+        net::register_net_imports, std::register_std_imports, SourceLinker,
     },
     wasm_store::{
         ObjectValue, OperationContext, OperationContextObject, RequestBuildingState, RequestState,
@@ -31,11 +33,24 @@ use self::{
     },
 };
 
+mod charset;
+pub mod cookie_jar;
+pub mod limits;
 pub mod model;
 mod source_settings;
 mod wasm_imports;
 mod wasm_store;
 
+pub use self::wasm_store::StoreMetrics;
+
+/// Fuel available to the start function of a source, when it's instantiated.
+const INSTANTIATION_FUEL: u64 = 100_000_000;
+
+/// How many values an instance of a source that doesn't import `std.retain` can hold before
+/// it's replaced by a new one. Their values are never reclaimed, so this is what keeps their
+/// memory usage bounded.
+const MAX_UNRECLAIMED_STD_VALUES: usize = 10_000;
+
 #[derive(Clone)]
 pub struct Source(
     /// In order to avoid issues when calling functions that block inside the `Source` from an
@@ -45,35 +60,104 @@ pub struct Source(
     /// program to panic (see https://github.com/seanmonstar/reqwest/issues/1017), and we do call
     /// them inside the `net` module.
     ///
-    /// This also provides interior mutability, but we probably could also do it inside the
-    /// `BlockingSource` itself, by placing things inside a mutex. It might be a cleaner design.
-    Arc<Mutex<BlockingSource>>,
+    /// Each `BlockingSource` can only run one operation at a time, so the pool keeps multiple
+    /// instances of the source around, allowing e.g. a download to progress while the chapter
+    /// list is being refreshed.
+    Arc<SourcePool>,
 );
 
 macro_rules! wrap_blocking_source_fn {
     ($fn_name:ident, $return_type:ty, $($param:ident : $type:ty),*) => {
         pub async fn $fn_name(&self, $($param: $type),*) -> $return_type {
-            let blocking_source = self.0.clone();
+            let pool = self.0.clone();
+            let permit = pool.permits().acquire_owned().await?;
 
-            ::tokio::task::spawn_blocking(move || blocking_source.lock().unwrap().$fn_name($($param),*)).await?
+            ::tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                let mut blocking_source = pool.checkout()?;
+
+                blocking_source.$fn_name($($param),*)
+            }).await?
         }
     };
 }
 
 impl Source {
-    pub fn from_aix_file(path: &Path, settings: Settings) -> Result<Self> {
-        let blocking_source = BlockingSource::from_aix_file(path, settings)?;
+    pub fn from_aix_file(
+        path: &Path,
+        settings: Settings,
+        connectivity_checker: Arc<ConnectivityChecker>,
+    ) -> Result<Self> {
+        let pool = SourcePool::from_aix_file(path, settings, connectivity_checker)?;
+
+        Ok(Self(Arc::new(pool)))
+    }
+
+    /// Checks that the file at `path` contains a source that can be loaded, without loading it.
+    pub fn validate_aix_file(path: &Path) -> Result<()> {
+        SourceManifest::from_aix_file(path)?;
+        CompiledSource::from_aix_file(path)?;
 
-        Ok(Self(Arc::new(Mutex::new(blocking_source))))
+        Ok(())
     }
 
     pub fn manifest(&self) -> SourceManifest {
         // FIXME we dont actually need to clone here but yeah it's easier
-        self.0.lock().unwrap().manifest.clone()
+        self.0.manifest.clone()
     }
 
     pub fn setting_definitions(&self) -> Vec<SettingDefinition> {
-        self.0.lock().unwrap().setting_definitions.clone()
+        self.0.setting_definitions.clone()
+    }
+
+    pub fn cookie_jar(&self) -> Arc<SourceCookieJar> {
+        self.0.cookie_jar.clone()
+    }
+
+    /// Makes new operations use `settings`. The compiled module is kept, only the instances of
+    /// the source are recreated (lazily, when needed) with the new settings.
+    pub fn update_settings(
+        &self,
+        settings: Settings,
+        connectivity_checker: Arc<ConnectivityChecker>,
+    ) {
+        let max_instances = settings.source_limits.max_instances();
+        if max_instances != self.0.current_settings().source_limits.max_instances() {
+            // Operations already running keep their permits from the previous semaphore, and
+            // their instances are discarded once they finish, as the settings changed.
+            *self
+                .0
+                .permits
+                .write()
+                .unwrap_or_else(PoisonError::into_inner) = Arc::new(Semaphore::new(max_instances));
+        }
+        *self
+            .0
+            .connectivity_checker
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = connectivity_checker;
+        *self
+            .0
+            .settings
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(settings);
+
+        self.0
+            .idle_instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Returns the metrics of all instances of the source that aren't currently in use.
+    pub fn store_metrics(&self) -> StoreMetrics {
+        self.0
+            .idle_instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|blocking_source| blocking_source.store.data().metrics())
+            .fold(StoreMetrics::default(), |total, metrics| total + metrics)
     }
 
     wrap_blocking_source_fn!(
@@ -126,15 +210,9 @@ pub struct SourceManifest {
     pub info: SourceInfo,
 }
 
-struct BlockingSource {
-    store: Store<WasmStore>,
-    instance: Instance,
-    manifest: SourceManifest,
-    setting_definitions: Vec<SettingDefinition>,
-}
-
-impl BlockingSource {
-    pub fn from_aix_file(path: &Path, settings: Settings) -> Result<Self> {
+impl SourceManifest {
+    /// Reads the manifest of the source at `path`, without loading the source itself.
+    pub fn from_aix_file(path: &Path) -> Result<Self> {
         let file =
             fs::File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
         let mut archive = ZipArchive::new(file)
@@ -143,8 +221,43 @@ impl BlockingSource {
         let manifest_file = archive
             .by_name("Payload/source.json")
             .with_context(|| "while loading source.json")?;
-        let manifest: SourceManifest = serde_json::from_reader(manifest_file)?;
 
+        Ok(serde_json::from_reader(manifest_file)?)
+    }
+}
+
+/// The parts of a source shared between all of its instances.
+struct SourcePool {
+    manifest: SourceManifest,
+    setting_definitions: Vec<SettingDefinition>,
+    /// The settings new instances are created with.
+    settings: RwLock<Arc<Settings>>,
+    /// Shared with all other sources, so that the connection is only checked once for all of
+    /// them.
+    connectivity_checker: RwLock<Arc<ConnectivityChecker>>,
+    cookie_jar: Arc<SourceCookieJar>,
+    /// Compiled (and checked) when loading the source, so that broken sources are noticed right
+    /// away. Instances are only created once the source is used.
+    compiled_source: CompiledSource,
+    /// Bounds how many operations can run at the same time, so that whoever holds a permit is
+    /// guaranteed to either find an idle instance or be allowed to create a new one. Replaced
+    /// when the maximum number of instances changes.
+    permits: RwLock<Arc<Semaphore>>,
+    idle_instances: Mutex<Vec<BlockingSource>>,
+}
+
+impl SourcePool {
+    fn from_aix_file(
+        path: &Path,
+        settings: Settings,
+        connectivity_checker: Arc<ConnectivityChecker>,
+    ) -> Result<Self> {
+        let manifest = SourceManifest::from_aix_file(path)?;
+
+        let file =
+            fs::File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+        let mut archive = ZipArchive::new(file)
+            .with_context(|| format!("couldn't open source archive {}", path.display()))?;
         let setting_definitions: Vec<SettingDefinition> =
             if let Ok(file) = archive.by_name("Payload/settings.json") {
                 serde_json::from_reader(file)?
@@ -152,25 +265,154 @@ impl BlockingSource {
                 Vec::new()
             };
 
-        let stored_source_settings = settings
-            .source_settings
-            .get(&manifest.info.id)
-            .cloned()
-            .unwrap_or_default();
+        let cookie_jar = SourceCookieJar::load(SourceCookieJar::path_for_source(path))?;
+        let compiled_source = CompiledSource::from_aix_file(path)?;
 
-        let source_settings = SourceSettings::new(&setting_definitions, stored_source_settings)?;
+        Ok(Self {
+            manifest,
+            setting_definitions,
+            permits: RwLock::new(Arc::new(Semaphore::new(
+                settings.source_limits.max_instances(),
+            ))),
+            settings: RwLock::new(Arc::new(settings)),
+            connectivity_checker: RwLock::new(connectivity_checker),
+            cookie_jar: Arc::new(cookie_jar),
+            compiled_source,
+            idle_instances: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn create_instance(&self) -> Result<BlockingSource> {
+        BlockingSource::new(self, &self.compiled_source, self.current_settings())
+    }
+
+    fn permits(&self) -> Arc<Semaphore> {
+        self.permits
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn current_settings(&self) -> Arc<Settings> {
+        self.settings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Takes an idle instance of the source, or creates a new one if there's none. Must only be
+    /// called while holding a permit.
+    fn checkout(&self) -> Result<PooledBlockingSource<'_>> {
+        let idle_instance = self
+            .idle_instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+
+        let blocking_source = match idle_instance {
+            Some(blocking_source) => blocking_source,
+            None => self.create_instance().with_context(|| {
+                format!("couldn't instantiate source {}", self.manifest.info.id)
+            })?,
+        };
+
+        Ok(PooledBlockingSource {
+            pool: self,
+            blocking_source: Some(blocking_source),
+        })
+    }
+}
+
+/// A `BlockingSource` taken from the pool, which is given back once dropped.
+struct PooledBlockingSource<'a> {
+    pool: &'a SourcePool,
+    blocking_source: Option<BlockingSource>,
+}
+
+impl Deref for PooledBlockingSource<'_> {
+    type Target = BlockingSource;
+
+    fn deref(&self) -> &Self::Target {
+        self.blocking_source.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledBlockingSource<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.blocking_source.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledBlockingSource<'_> {
+    fn drop(&mut self) {
+        let Some(blocking_source) = self.blocking_source.take() else {
+            return;
+        };
+
+        // If the operation panicked or the WASM code trapped, the instance might have been left
+        // in an inconsistent state, so we throw it away and let a new one be created when needed.
+        if std::thread::panicking() || blocking_source.trapped {
+            warn!(
+                "discarding instance of source {} after a failed operation",
+                self.pool.manifest.info.id
+            );
+
+            return;
+        }
+
+        // The settings changed while the instance was in use.
+        if !Arc::ptr_eq(&blocking_source.settings, &self.pool.current_settings()) {
+            return;
+        }
+
+        let wasm_store = blocking_source.store.data();
+        if !wasm_store.reclaims_std_values
+            && wasm_store.metrics().std_descriptors >= MAX_UNRECLAIMED_STD_VALUES
+        {
+            debug!(
+                "retiring instance of source {} holding too many values",
+                self.pool.manifest.info.id
+            );
+
+            return;
+        }
+
+        self.pool
+            .idle_instances
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(blocking_source);
+    }
+}
+
+/// The WASM code of a source, compiled and ready to be instantiated.
+struct CompiledSource {
+    engine: Engine,
+    module: Module,
+    linker: Linker<WasmStore>,
+}
+
+impl CompiledSource {
+    fn from_aix_file(path: &Path) -> Result<Self> {
+        let file =
+            fs::File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+        let mut archive = ZipArchive::new(file)
+            .with_context(|| format!("couldn't open source archive {}", path.display()))?;
 
         let wasm_file = archive
             .by_name("Payload/main.wasm")
             .with_context(|| "while loading main.wasm")?;
 
-        let engine = Engine::default();
-        let wasm_store = WasmStore::new(manifest.info.id.clone(), source_settings, settings);
-        let mut store = Store::new(&engine, wasm_store);
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        // The code is still validated right away, but only translated once it's first called,
+        // which keeps loading all installed sources fast.
+        config.compilation_mode(CompilationMode::LazyTranslation);
+        let engine = Engine::new(&config);
         let module = Module::new_streaming(&engine, wasm_file)
             .with_context(|| format!("failed loading module from {}", path.display()))?;
 
-        let mut linker = Linker::new(&engine);
+        let mut linker = SourceLinker::new(&engine);
         register_aidoku_imports(&mut linker)?;
         register_defaults_imports(&mut linker)?;
         register_env_imports(&mut linker)?;
@@ -179,28 +421,102 @@ impl BlockingSource {
         register_net_imports(&mut linker)?;
         register_std_imports(&mut linker)?;
 
+        linker
+            .check_imports(&module)
+            .with_context(|| format!("failed linking module from {}", path.display()))?;
+
+        Ok(Self {
+            engine,
+            module,
+            linker: linker.into_linker(),
+        })
+    }
+}
+
+/// Whether `error` interrupted the execution of the WASM code, either because it trapped (which
+/// includes running out of fuel or memory) or because an import failed.
+fn aborted_execution(error: &wasmi::Error) -> bool {
+    error.as_trap_code().is_some() || error.downcast_ref::<ImportError>().is_some()
+}
+
+struct BlockingSource {
+    store: Store<WasmStore>,
+    instance: Instance,
+    /// The settings the instance was created with.
+    settings: Arc<Settings>,
+    /// Set when a call into the WASM code was aborted midway, which might have left the
+    /// instance in an inconsistent state.
+    trapped: bool,
+}
+
+impl BlockingSource {
+    fn new(
+        pool: &SourcePool,
+        compiled_source: &CompiledSource,
+        settings: Arc<Settings>,
+    ) -> Result<Self> {
+        let SourcePool {
+            manifest,
+            setting_definitions,
+            cookie_jar,
+            connectivity_checker,
+            ..
+        } = pool;
+        let CompiledSource {
+            engine,
+            module,
+            linker,
+        } = compiled_source;
+
+        let stored_source_settings = settings
+            .source_settings
+            .get(&manifest.info.id)
+            .cloned()
+            .unwrap_or_default();
+
+        let source_settings = SourceSettings::new(setting_definitions, stored_source_settings)?;
+
+        let mut wasm_store = WasmStore::new(
+            manifest.info.id.clone(),
+            source_settings,
+            (*settings).clone(),
+            cookie_jar.clone(),
+            connectivity_checker
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        )?;
+        wasm_store.reclaims_std_values = module
+            .imports()
+            .any(|import| import.module() == "std" && import.name() == "retain");
+        let mut store = Store::new(engine, wasm_store);
+        store.limiter(|wasm_store| &mut wasm_store.limits);
+        store
+            .set_fuel(INSTANTIATION_FUEL)
+            .map_err(|e| anyhow!("couldn't set the source fuel: {e}"))?;
+
         let instance = linker
-            .instantiate(&mut store, &module)
-            .with_context(|| {
-                format!(
-                    "failed creating instance when loading from {}",
-                    path.display()
-                )
-            })?
+            .instantiate(&mut store, module)
+            .with_context(|| format!("failed creating instance of source {}", manifest.info.id))?
             .start(&mut store)?;
 
         Ok(Self {
             store,
             instance,
-            manifest,
-            setting_definitions,
+            settings,
+            trapped: false,
         })
     }
 
     pub fn get_manga_list(&mut self, cancellation_token: CancellationToken) -> Result<Vec<Manga>> {
-        self.run_under_context(cancellation_token, OperationContextObject::None, |this| {
-            this.search_mangas_by_filters_inner(vec![])
-        })
+        let fuel = self.store.data().settings.source_limits.search_fuel();
+
+        self.run_under_context(
+            cancellation_token,
+            OperationContextObject::None,
+            fuel,
+            |this| this.search_mangas_by_filters_inner(vec![]),
+        )
     }
 
     pub fn search_mangas(
@@ -208,9 +524,14 @@ impl BlockingSource {
         cancellation_token: CancellationToken,
         query: String,
     ) -> Result<Vec<Manga>> {
-        self.run_under_context(cancellation_token, OperationContextObject::None, |this| {
-            this.search_mangas_by_filters_inner(vec![Filter::Title(query)])
-        })
+        let fuel = self.store.data().settings.source_limits.search_fuel();
+
+        self.run_under_context(
+            cancellation_token,
+            OperationContextObject::None,
+            fuel,
+            |this| this.search_mangas_by_filters_inner(vec![Filter::Title(query)]),
+        )
     }
 
     fn search_mangas_by_filters_inner(&mut self, filters: Vec<Filter>) -> Result<Vec<Manga>> {
@@ -230,8 +551,9 @@ impl BlockingSource {
 
         // FIXME what if i actually want more pages tho
         let page = 1i32;
-        let page_descriptor =
-            wasm_function.call(&mut self.store, (filters_descriptor as i32, page))?;
+        let page_descriptor = wasm_function
+            .call(&mut self.store, (filters_descriptor as i32, page))
+            .map_err(|e| self.wasm_call_error("get_manga_list", e))?;
         // TODO maybe use some `TryInto` implementation here to make things easier to read
         let mangas: Vec<Manga> = match self
             .store
@@ -249,8 +571,6 @@ impl BlockingSource {
             ),
         };
 
-        // TODO remove page_descriptor and filters_descriptor from the source's storage
-
         Ok(mangas)
     }
 
@@ -259,11 +579,14 @@ impl BlockingSource {
         cancellation_token: CancellationToken,
         manga_id: String,
     ) -> Result<Vec<Chapter>> {
+        let fuel = self.store.data().settings.source_limits.chapter_list_fuel();
+
         self.run_under_context(
             cancellation_token,
             OperationContextObject::Manga {
                 id: manga_id.clone(),
             },
+            fuel,
             |this| this.get_chapter_list_inner(manga_id),
         )
     }
@@ -284,8 +607,9 @@ impl BlockingSource {
         let wasm_function = self
             .instance
             .get_typed_func::<i32, i32>(&mut self.store, "get_chapter_list")?;
-        let chapter_list_descriptor =
-            wasm_function.call(&mut self.store, manga_descriptor as i32)?;
+        let chapter_list_descriptor = wasm_function
+            .call(&mut self.store, manga_descriptor as i32)
+            .map_err(|e| self.wasm_call_error("get_chapter_list", e))?;
 
         let chapters: Vec<Chapter> = match self
             .store
@@ -318,11 +642,14 @@ impl BlockingSource {
         chapter_id: String,
         chapter_num: Option<f64>,
     ) -> Result<Vec<Page>> {
+        let fuel = self.store.data().settings.source_limits.page_list_fuel();
+
         self.run_under_context(
             cancellation_token,
             OperationContextObject::Chapter {
                 id: chapter_id.clone(),
             },
+            fuel,
             |this| this.get_page_list_inner(manga_id, chapter_id, chapter_num),
         )
     }
@@ -355,8 +682,9 @@ impl BlockingSource {
         let wasm_function = self
             .instance
             .get_typed_func::<i32, i32>(&mut self.store, "get_page_list")?;
-        let page_list_descriptor =
-            wasm_function.call(&mut self.store, chapter_descriptor as i32)?;
+        let page_list_descriptor = wasm_function
+            .call(&mut self.store, chapter_descriptor as i32)
+            .map_err(|e| self.wasm_call_error("get_page_list", e))?;
 
         let pages: Vec<Page> = match self
             .store
@@ -383,7 +711,16 @@ impl BlockingSource {
     }
 
     pub fn get_image_request(&mut self, url: Url) -> Result<Request> {
+        let checkpoint = self.store.data().checkpoint();
+        let result = self.get_image_request_inner(url);
+        self.store.data_mut().reclaim_since(checkpoint);
+
+        result
+    }
+
+    fn get_image_request_inner(&mut self, url: Url) -> Result<Request> {
         let request_descriptor = self.store.data_mut().create_request();
+        let cookies = self.store.data().cookie_jar.header_value_for(&url);
 
         // FIXME scoping here is so fucking scuffed
         {
@@ -391,23 +728,30 @@ impl BlockingSource {
                 .store
                 .data_mut()
                 .get_mut_request(request_descriptor)
-                .unwrap();
+                .context("image request was not created")?;
 
-            let request_building_state = match request_state {
-                RequestState::Building(building_state) => Some(building_state),
-                _ => None,
-            }
-            .unwrap();
+            let RequestState::Building(request_building_state) = request_state else {
+                bail!("image request is not in building state");
+            };
 
             request_building_state.method = Some(Method::GET);
             request_building_state.url = Some(url);
 
-            request_building_state
-                .headers
-                .insert("User-Agent".to_string(), DEFAULT_USER_AGENT.to_string());
+            if let Some(cookies) = cookies {
+                request_building_state
+                    .headers
+                    .insert("Cookie".to_string(), cookies);
+            }
         };
 
-        // TODO add support for cookies
+        let fuel = self
+            .store
+            .data()
+            .settings
+            .source_limits
+            .image_request_fuel();
+        self.set_fuel(fuel)?;
+
         // it seems that it's fine for an extension to not have this function defined, so we only
         // call it if it exists
         {
@@ -417,7 +761,9 @@ impl BlockingSource {
                 .instance
                 .get_typed_func::<i32, ()>(&mut wasm_store, "modify_image_request")
             {
-                wasm_function.call(&mut wasm_store, request_descriptor as i32)?;
+                wasm_function
+                    .call(&mut wasm_store, request_descriptor as i32)
+                    .map_err(|e| self.wasm_call_error("modify_image_request", e))?;
             }
         }
 
@@ -425,35 +771,189 @@ impl BlockingSource {
             .store
             .data_mut()
             .get_mut_request(request_descriptor)
-            .unwrap();
+            .context("image request was removed by modify_image_request")?;
 
-        let request_building_state = match request_state {
-            RequestState::Building(building_state) => Some(building_state),
-            _ => None,
-        }
-        .unwrap();
+        let RequestState::Building(request_building_state) = request_state else {
+            bail!("image request is no longer in building state after modify_image_request");
+        };
 
         (request_building_state as &RequestBuildingState).try_into()
     }
 
+    fn wasm_call_error(
+        &mut self,
+        function_name: &'static str,
+        error: wasmi::Error,
+    ) -> anyhow::Error {
+        // Errors returned before the execution started (e.g. a function with an unexpected
+        // signature) leave the instance untouched, so it can still be reused.
+        self.trapped |= aborted_execution(&error);
+        let source_id = self.store.data().id.clone();
+
+        match limits::exhausted_resource(&error) {
+            Some(resource) => anyhow::Error::new(error).context(ResourceExhaustedError {
+                source_id,
+                function_name,
+                resource,
+            }),
+            None => anyhow::Error::new(error).context(format!(
+                "source {source_id} failed while running {function_name}"
+            )),
+        }
+    }
+
+    fn set_fuel(&mut self, fuel: u64) -> Result<()> {
+        self.store
+            .set_fuel(fuel)
+            .map_err(|e| anyhow!("couldn't set the source fuel: {e}"))
+    }
+
     fn run_under_context<T, F>(
         &mut self,
         cancellation_token: CancellationToken,
         current_object: OperationContextObject,
+        fuel: u64,
         f: F,
-    ) -> T
+    ) -> Result<T>
     where
-        F: FnOnce(&mut Self) -> T,
+        F: FnOnce(&mut Self) -> Result<T>,
     {
+        self.set_fuel(fuel)?;
         self.store.data_mut().context = OperationContext {
             cancellation_token,
             current_object,
+            ..Default::default()
         };
+        let checkpoint = self.store.data().checkpoint();
 
         let result = f(self);
 
-        self.store.data_mut().context = OperationContext::default();
+        let context = std::mem::take(&mut self.store.data_mut().context);
+        self.store.data_mut().reclaim_since(checkpoint);
 
-        result
+        // The source itself only sees a failed request, so we attach the timeout to the error
+        // in order to make it distinguishable from other failures.
+        match (result, context.request_timeout) {
+            (Err(e), Some(request_timeout)) => Err(e.context(request_timeout)),
+            (result, _) => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::tempdir;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    const EMPTY_WASM_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+    // Imports `env.nope`, a function with no parameters or results.
+    const WASM_MODULE_WITH_UNKNOWN_IMPORT: &[u8] =
+        b"\0asm\x01\0\0\0\x01\x04\x01\x60\0\0\x02\x0c\x01\x03env\x04nope\0\0";
+
+    fn write_source(path: &Path, wasm_module: &[u8]) -> Result<()> {
+        let mut writer = ZipWriter::new(fs::File::create(path)?);
+        writer.start_file("Payload/source.json", FileOptions::default())?;
+        writer.write_all(br#"{"info":{"id":"en.test","lang":"en","name":"Test","version":1}}"#)?;
+        writer.start_file("Payload/main.wasm", FileOptions::default())?;
+        writer.write_all(wasm_module)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+
+    fn settings_with_max_instances(max_instances: usize) -> Settings {
+        let mut settings = Settings::default();
+        settings.source_limits.max_instances = Some(max_instances);
+
+        settings
+    }
+
+    fn idle_instances(source: &Source) -> usize {
+        source.0.idle_instances.lock().unwrap().len()
+    }
+
+    #[test]
+    fn it_reuses_instances_unless_they_trapped() -> Result<()> {
+        let folder = tempdir()?;
+        let path = folder.path().join("en.test.aix");
+        write_source(&path, EMPTY_WASM_MODULE)?;
+        let source =
+            Source::from_aix_file(&path, settings_with_max_instances(2), Default::default())?;
+
+        drop(source.0.checkout()?);
+        assert_eq!(idle_instances(&source), 1);
+
+        let mut instance = source.0.checkout()?;
+        assert_eq!(idle_instances(&source), 0);
+        instance.trapped = true;
+        drop(instance);
+        assert_eq!(idle_instances(&source), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_retires_instances_holding_too_many_unreclaimed_values() -> Result<()> {
+        let folder = tempdir()?;
+        let path = folder.path().join("en.test.aix");
+        write_source(&path, EMPTY_WASM_MODULE)?;
+        let source =
+            Source::from_aix_file(&path, settings_with_max_instances(1), Default::default())?;
+
+        // Each operation leaves values behind, since the source doesn't import `std.retain`.
+        for _ in 0..3 * MAX_UNRECLAIMED_STD_VALUES / 1000 {
+            let mut instance = source.0.checkout()?;
+            assert!(!instance.store.data().reclaims_std_values);
+            for _ in 0..1000 {
+                instance
+                    .store
+                    .data_mut()
+                    .store_std_value(Value::Null.into(), None);
+            }
+            drop(instance);
+
+            assert!(source.store_metrics().std_descriptors < MAX_UNRECLAIMED_STD_VALUES);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_does_not_load_sources_with_unknown_imports() -> Result<()> {
+        let folder = tempdir()?;
+        let path = folder.path().join("en.test.aix");
+        write_source(&path, WASM_MODULE_WITH_UNKNOWN_IMPORT)?;
+
+        let Err(error) = Source::from_aix_file(&path, Settings::default(), Default::default())
+        else {
+            panic!("expected the source to fail to load");
+        };
+
+        assert!(format!("{error:#}").contains("failed linking module"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_follows_the_instance_limit_from_the_settings() -> Result<()> {
+        let folder = tempdir()?;
+        let path = folder.path().join("en.test.aix");
+        write_source(&path, EMPTY_WASM_MODULE)?;
+        let source =
+            Source::from_aix_file(&path, settings_with_max_instances(2), Default::default())?;
+
+        let permits = source.0.permits();
+        let _first = permits.clone().try_acquire_owned()?;
+        let _second = permits.clone().try_acquire_owned()?;
+        assert!(permits.try_acquire_owned().is_err());
+
+        source.update_settings(settings_with_max_instances(3), Default::default());
+        assert_eq!(source.0.permits().available_permits(), 3);
+
+        Ok(())
     }
 }
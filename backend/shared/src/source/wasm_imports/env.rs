@@ -5,13 +5,17 @@ use wasm_shared::{
     get_memory,
     memory_reader::{read_bytes, read_string},
 };
-use wasmi::{core::HostError, Caller, Linker};
+use wasmi::{
+    core::{HostError, ValType},
+    Caller, FuncType,
+};
 
-use crate::source::wasm_store::WasmStore;
+use crate::source::{wasm_imports::SourceLinker, wasm_store::WasmStore};
 
-pub fn register_env_imports(linker: &mut Linker<WasmStore>) -> Result<()> {
+pub fn register_env_imports(linker: &mut SourceLinker) -> Result<()> {
     register_wasm_function!(linker, "env", "print", print)?;
-    linker.func_wrap("env", "abort", abort)?;
+    // `abort` traps the source instead of returning an error to it, so it's defined directly.
+    linker.func_wrap("env", "abort", FuncType::new([ValType::I32; 4], []), abort)?;
 
     Ok(())
 }
@@ -46,7 +50,8 @@ fn abort(
     // For some stupid reason, unlike _all_ of the Aidoku WASM function exports, this
     // specifically receives the offsets of the beginning of the stream, and the length comes
     // before the offset (?)
-    let memory = get_memory(&mut caller).unwrap();
+    let memory = get_memory(&mut caller)
+        .ok_or_else(|| wasmi::Error::new("env.abort: could not get WASM memory"))?;
     let msg_length = read_bytes(&memory, &caller, (msg_offset - 4) as usize, 1)
         .and_then(|bytes| bytes.first().cloned())
         .unwrap_or(0) as usize;
@@ -1,13 +1,19 @@
 use anyhow::{anyhow, Context, Result};
 
-use scraper::{Element, Html as ScraperHtml, Node, Selector};
+use ego_tree::NodeId;
+use pared::sync::Parc;
+use scraper::{Element, ElementRef, Html as ScraperHtml, Node, Selector};
 use url::Url;
 use wasm_macros::{aidoku_wasm_function, register_wasm_function};
-use wasmi::{Caller, Linker};
+use wasmi::Caller;
 
-use crate::source::wasm_store::{HTMLElement, Html, Value, WasmStore};
+use crate::source::{
+    charset,
+    wasm_imports::SourceLinker,
+    wasm_store::{HTMLElement, Html, Value, WasmStore},
+};
 
-pub fn register_html_imports(linker: &mut Linker<WasmStore>) -> Result<()> {
+pub fn register_html_imports(linker: &mut SourceLinker) -> Result<()> {
     register_wasm_function!(linker, "html", "parse", parse)?;
     register_wasm_function!(linker, "html", "parse_fragment", parse_fragment)?;
     register_wasm_function!(linker, "html", "parse_with_uri", parse_with_uri)?;
@@ -53,7 +59,7 @@ pub fn register_html_imports(linker: &mut Linker<WasmStore>) -> Result<()> {
 }
 
 #[aidoku_wasm_function]
-fn parse(caller: Caller<'_, WasmStore>, data: Option<String>) -> Result<i32> {
+fn parse(caller: Caller<'_, WasmStore>, data: Option<Vec<u8>>) -> Result<i32> {
     parse_with_uri(caller, data, None)
 }
 
@@ -65,26 +71,38 @@ fn parse_fragment(caller: Caller<'_, WasmStore>, data: Option<String>) -> Result
 #[aidoku_wasm_function]
 fn parse_with_uri(
     mut caller: Caller<'_, WasmStore>,
-    data: Option<String>,
+    data: Option<Vec<u8>>,
     uri: Option<String>,
 ) -> Result<i32> {
-    let document =
-        ScraperHtml::parse_document(&data.context("data is required for parse_with_uri")?);
-    let node_id = document.root_element().id();
+    let data = data.context("data is required for parse_with_uri")?;
     let uri = uri
         .map(|u| Url::parse(&u).context("invalid URI"))
         .transpose()?;
-    let html_element = HTMLElement {
-        document: Html::from(document).into(),
-        node_id,
-        base_uri: uri,
-    };
+    let html_element = parse_document_bytes(&data, uri);
 
     let wasm_store = caller.data_mut();
 
     Ok(wasm_store.store_std_value(Value::from(vec![html_element]).into(), None) as i32)
 }
 
+/// Parses a full HTML document given as raw bytes (e.g. a response body read by the source),
+/// decoding it with the charset declared in the document.
+fn parse_document_bytes(html: &[u8], base_uri: Option<Url>) -> HTMLElement {
+    parse_document(&charset::decode_html(html, None), base_uri)
+}
+
+/// Parses a full HTML document, returning its root element.
+pub fn parse_document(html: &str, base_uri: Option<Url>) -> HTMLElement {
+    let document = ScraperHtml::parse_document(html);
+    let node_id = document.root_element().id();
+
+    HTMLElement {
+        document: Html::from(document).into(),
+        node_id,
+        base_uri,
+    }
+}
+
 #[aidoku_wasm_function]
 fn parse_fragment_with_uri(
     mut caller: Caller<'_, WasmStore>,
@@ -207,31 +225,219 @@ fn attr(
 
 #[aidoku_wasm_function]
 fn set_text(
-    mut _caller: Caller<'_, WasmStore>,
+    mut caller: Caller<'_, WasmStore>,
     descriptor_i32: i32,
     text: Option<String>,
 ) -> Result<i32> {
-    let _descriptor: usize = descriptor_i32
+    let descriptor: usize = descriptor_i32
         .try_into()
         .context("failed to convert to descriptor")?;
-    let _text = text.context("text is required")?;
+    let text = text.context("text is required")?;
+    let html = html_escape::encode_text(&text);
 
-    todo!("modifying the HTML document is unsupported")
+    modify_elements(caller.data_mut(), descriptor, |document, node_id| {
+        set_inner_html(document, node_id, &html)
+    })?;
+
+    Ok(0)
 }
 
 #[aidoku_wasm_function]
-fn set_html(_caller: Caller<'_, WasmStore>, _descriptor_i32: i32, _text: Option<String>) -> i32 {
-    todo!("modifying the HTML document is unsupported")
+fn set_html(
+    mut caller: Caller<'_, WasmStore>,
+    descriptor_i32: i32,
+    html: Option<String>,
+) -> Result<i32> {
+    let descriptor: usize = descriptor_i32.try_into().context("invalid descriptor")?;
+    let html = html.context("html is required")?;
+
+    modify_elements(caller.data_mut(), descriptor, |document, node_id| {
+        set_inner_html(document, node_id, &html)
+    })?;
+
+    Ok(0)
 }
 
 #[aidoku_wasm_function]
-fn prepend(_caller: Caller<'_, WasmStore>, _descriptor_i32: i32, _text: Option<String>) -> i32 {
-    todo!("modifying the HTML document is unsupported")
+fn prepend(
+    mut caller: Caller<'_, WasmStore>,
+    descriptor_i32: i32,
+    html: Option<String>,
+) -> Result<i32> {
+    let descriptor: usize = descriptor_i32.try_into().context("invalid descriptor")?;
+    let html = html.context("html is required")?;
+
+    modify_elements(caller.data_mut(), descriptor, |document, node_id| {
+        prepend_html(document, node_id, &html)
+    })?;
+
+    Ok(0)
 }
 
 #[aidoku_wasm_function]
-fn append(_caller: Caller<'_, WasmStore>, _descriptor_i32: i32, _text: Option<String>) -> i32 {
-    todo!("modifying the HTML document is unsupported")
+fn append(
+    mut caller: Caller<'_, WasmStore>,
+    descriptor_i32: i32,
+    html: Option<String>,
+) -> Result<i32> {
+    let descriptor: usize = descriptor_i32.try_into().context("invalid descriptor")?;
+    let html = html.context("html is required")?;
+
+    modify_elements(caller.data_mut(), descriptor, |document, node_id| {
+        append_html(document, node_id, &html)
+    })?;
+
+    Ok(0)
+}
+
+/// Applies `modify` to every element stored in `descriptor`.
+///
+/// Documents are shared by every element selected from them. A document only referenced by the
+/// elements in `descriptor` is modified in place; any other is copied once, modified, and then
+/// replaced everywhere it's referenced.
+fn modify_elements<F>(wasm_store: &mut WasmStore, descriptor: usize, modify: F) -> Result<()>
+where
+    F: Fn(&mut ScraperHtml, NodeId) -> Result<()>,
+{
+    let std_value = wasm_store
+        .take_std_value(descriptor)
+        .context("failed to get value from store")?;
+    let elements = match Parc::unwrap_or_clone(std_value) {
+        Value::HTMLElements(elements) => elements,
+        value => {
+            wasm_store.set_std_value(descriptor, value.into());
+
+            return Err(anyhow!("expected HTMLElements value"));
+        }
+    };
+
+    // Split the elements from their documents, so that each document is referenced once here.
+    let mut documents: Vec<(Parc<Html>, Vec<NodeId>)> = Vec::new();
+    let mut element_positions = Vec::with_capacity(elements.len());
+    for element in elements {
+        let index = match documents
+            .iter()
+            .position(|(document, _)| element.belongs_to(document))
+        {
+            Some(index) => {
+                documents[index].1.push(element.node_id);
+
+                index
+            }
+            None => {
+                documents.push((element.document, vec![element.node_id]));
+
+                documents.len() - 1
+            }
+        };
+
+        element_positions.push((index, element.node_id, element.base_uri));
+    }
+
+    let mut result = Ok(());
+    let mut modified_documents = Vec::with_capacity(documents.len());
+    for (document, node_ids) in documents {
+        let apply = |html: &mut ScraperHtml| {
+            node_ids
+                .iter()
+                .try_for_each(|&node_id| modify(html, node_id))
+        };
+
+        let modified_document = if Parc::strong_count(&document) == 1 {
+            let mut html = Parc::unwrap_or_clone(document).into_inner();
+            result = result.and_then(|_| apply(&mut html));
+
+            Html::from(html).into()
+        } else {
+            let mut html = ScraperHtml::clone(&document);
+            result = result.and_then(|_| apply(&mut html));
+
+            if result.is_ok() {
+                let modified_document: Parc<Html> = Html::from(html).into();
+                wasm_store.replace_html_document(&document, modified_document.clone());
+
+                modified_document
+            } else {
+                document
+            }
+        };
+
+        modified_documents.push(modified_document);
+    }
+
+    let elements = element_positions
+        .into_iter()
+        .map(|(index, node_id, base_uri)| HTMLElement {
+            document: modified_documents[index].clone(),
+            node_id,
+            base_uri,
+        })
+        .collect::<Vec<_>>();
+    wasm_store.set_std_value(descriptor, Value::from(elements).into());
+
+    result
+}
+
+fn set_inner_html(document: &mut ScraperHtml, node_id: NodeId, html: &str) -> Result<()> {
+    let children: Vec<NodeId> = document
+        .tree
+        .get(node_id)
+        .context("element not found in document")?
+        .children()
+        .map(|child| child.id())
+        .collect();
+
+    for child in children {
+        document.tree.get_mut(child).unwrap().detach();
+    }
+
+    append_html(document, node_id, html)
+}
+
+fn append_html(document: &mut ScraperHtml, node_id: NodeId, html: &str) -> Result<()> {
+    let new_children = parse_fragment_into(document, html);
+    let mut node = document
+        .tree
+        .get_mut(node_id)
+        .context("element not found in document")?;
+
+    for child in new_children {
+        node.append_id(child);
+    }
+
+    Ok(())
+}
+
+fn prepend_html(document: &mut ScraperHtml, node_id: NodeId, html: &str) -> Result<()> {
+    let new_children = parse_fragment_into(document, html);
+    let mut node = document
+        .tree
+        .get_mut(node_id)
+        .context("element not found in document")?;
+
+    for child in new_children.into_iter().rev() {
+        node.prepend_id(child);
+    }
+
+    Ok(())
+}
+
+/// Parses `html` as a fragment and adds its nodes to the document, returning the IDs of its
+/// top-level nodes. Those are left detached, so they can be inserted wherever needed.
+fn parse_fragment_into(document: &mut ScraperHtml, html: &str) -> Vec<NodeId> {
+    let fragment = ScraperHtml::parse_fragment(html);
+    let fragment_root_id = document.tree.extend_tree(fragment.tree).id();
+
+    // Fragments are parsed into a `<html>` element placed under the fragment's root node.
+    document
+        .tree
+        .get(fragment_root_id)
+        .unwrap()
+        .children()
+        .filter(|node| node.value().is_element())
+        .flat_map(|container| container.children())
+        .map(|node| node.id())
+        .collect()
 }
 
 #[aidoku_wasm_function]
@@ -443,8 +649,46 @@ fn own_text(mut caller: Caller<'_, WasmStore>, descriptor_i32: i32) -> Result<i3
 }
 
 #[aidoku_wasm_function]
-fn data(_caller: Caller<'_, WasmStore>, _descriptor_i32: i32) -> i32 {
-    todo!("yeah idk man")
+fn data(mut caller: Caller<'_, WasmStore>, descriptor_i32: i32) -> Result<i32> {
+    let descriptor: usize = descriptor_i32.try_into().context("invalid descriptor")?;
+
+    let wasm_store = caller.data_mut();
+    let std_value = wasm_store
+        .get_std_value(descriptor)
+        .context("failed to get value from store")?;
+    let elements = match std_value.as_ref() {
+        Value::HTMLElements(elements) => Some(elements),
+        _ => None,
+    }
+    .context("expected HTMLElements value")?;
+
+    let data = elements
+        .iter()
+        .map(|element| element_data(element.element_ref()))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(wasm_store.store_std_value(Value::from(data).into(), Some(descriptor)) as i32)
+}
+
+/// Returns the data inside an element, following jsoup's semantics: the contents of `<script>`
+/// and `<style>` elements and of comments, including the ones inside child elements. Unlike
+/// the element's text, this doesn't include any regular text nodes.
+fn element_data(element: ElementRef) -> String {
+    element
+        .descendants()
+        .filter_map(|node| match node.value() {
+            Node::Text(text) => {
+                let parent_name = node
+                    .parent()
+                    .and_then(|parent| parent.value().as_element().map(|e| e.name()));
+
+                matches!(parent_name, Some("script" | "style")).then_some(&**text)
+            }
+            Node::Comment(comment) => Some(&**comment),
+            _ => None,
+        })
+        .collect()
 }
 
 #[aidoku_wasm_function]
@@ -711,3 +955,112 @@ fn has_attr(
 
     Ok(if has_attr { 1 } else { 0 })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body_id(document: &ScraperHtml) -> NodeId {
+        let selector = Selector::parse("body").unwrap();
+
+        document.select(&selector).next().unwrap().id()
+    }
+
+    fn body_html(document: &ScraperHtml) -> String {
+        let selector = Selector::parse("body").unwrap();
+
+        document.select(&selector).next().unwrap().inner_html()
+    }
+
+    #[test]
+    fn it_returns_script_and_comment_data() {
+        let document = ScraperHtml::parse_document(
+            "<body><script>var data = {\"a\": 1};</script><p>text</p><!-- comment --></body>",
+        );
+        let body = document
+            .select(&Selector::parse("body").unwrap())
+            .next()
+            .unwrap();
+
+        assert_eq!(element_data(body), "var data = {\"a\": 1}; comment ");
+    }
+
+    #[test]
+    fn it_decodes_documents_with_the_declared_charset() {
+        let html_element = parse_document_bytes(
+            b"<html><head><meta charset=\"iso-8859-1\"></head><body>Cap\xedtulo</body></html>",
+            None,
+        );
+        let body = html_element
+            .element_ref()
+            .select(&Selector::parse("body").unwrap())
+            .next()
+            .unwrap();
+
+        assert_eq!(body.text().collect::<String>(), "Cap\u{ed}tulo");
+    }
+
+    #[test]
+    fn it_modifies_documents_shared_with_other_values() -> Result<()> {
+        let mut wasm_store = WasmStore::default();
+        let owned = parse_document("<body><p>a</p></body>", None);
+        let shared = parse_document("<body><p>b</p></body>", None);
+        let owned_descriptor = wasm_store.store_std_value(Value::from(vec![owned]).into(), None);
+        let shared_descriptor =
+            wasm_store.store_std_value(Value::from(vec![shared.clone()]).into(), None);
+        let other_descriptor = wasm_store.store_std_value(Value::from(vec![shared]).into(), None);
+
+        for descriptor in [owned_descriptor, shared_descriptor] {
+            modify_elements(&mut wasm_store, descriptor, |document, node_id| {
+                append_html(document, node_id, "<p>c</p>")
+            })?;
+        }
+
+        let html_of = |descriptor| {
+            let value = wasm_store.get_std_value(descriptor).unwrap();
+            let element = &value.try_unwrap_html_elements_ref().unwrap()[0];
+
+            element.element_ref().inner_html()
+        };
+        assert_eq!(
+            html_of(owned_descriptor),
+            "<head></head><body><p>a</p></body><p>c</p>"
+        );
+        assert_eq!(
+            html_of(shared_descriptor),
+            "<head></head><body><p>b</p></body><p>c</p>"
+        );
+        assert_eq!(html_of(other_descriptor), html_of(shared_descriptor));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_appends_and_prepends_html() {
+        let mut document = ScraperHtml::parse_document("<body><p>middle</p></body>");
+        let body_id = body_id(&document);
+
+        append_html(&mut document, body_id, "<p>last</p>").unwrap();
+        prepend_html(&mut document, body_id, "<p>first</p>text").unwrap();
+
+        assert_eq!(
+            body_html(&document),
+            "<p>first</p>text<p>middle</p><p>last</p>"
+        );
+    }
+
+    #[test]
+    fn it_replaces_the_inner_html() {
+        let mut document =
+            ScraperHtml::parse_document("<body><div class=\"ad\">buy now</div><p>a</p></body>");
+        let body_id = body_id(&document);
+
+        set_inner_html(&mut document, body_id, "<p>b</p>").unwrap();
+
+        assert_eq!(body_html(&document), "<p>b</p>");
+        assert!(document
+            .select(&Selector::parse(".ad").unwrap())
+            .next()
+            .is_none());
+    }
+}
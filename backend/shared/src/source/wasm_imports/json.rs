@@ -1,11 +1,14 @@
 use anyhow::{Context, Result};
 use serde::de::{Deserialize, MapAccess, Visitor};
 use wasm_macros::{aidoku_wasm_function, register_wasm_function};
-use wasmi::{Caller, Linker};
+use wasmi::Caller;
 
-use crate::source::wasm_store::{ObjectValue, Value, ValueMap, WasmStore};
+use crate::source::{
+    wasm_imports::SourceLinker,
+    wasm_store::{ObjectValue, Value, ValueMap, WasmStore},
+};
 
-pub fn register_json_imports(linker: &mut Linker<WasmStore>) -> Result<()> {
+pub fn register_json_imports(linker: &mut SourceLinker) -> Result<()> {
     register_wasm_function!(linker, "json", "parse", parse)?;
 
     Ok(())
@@ -55,8 +58,11 @@ impl<'de> Deserialize<'de> for Value {
             where
                 E: serde::de::Error,
             {
-                // FIXME no
-                Ok(Value::Int(v.try_into().map_err(|_| todo!())?))
+                let v = v
+                    .try_into()
+                    .map_err(|_| E::custom(format!("integer {v} does not fit in a i64")))?;
+
+                Ok(Value::Int(v))
             }
 
             fn visit_f64<E>(self, v: f64) -> std::result::Result<Self::Value, E>
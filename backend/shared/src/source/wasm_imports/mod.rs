@@ -1,5 +1,15 @@
 extern crate wasm_macros;
 
+use ::std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use wasm_shared::HostFunctionLinker;
+use wasmi::{
+    errors::LinkerError, Caller, Engine, ExternType, FuncType, IntoFunc, Linker, Module, Val,
+};
+
+use super::wasm_store::WasmStore;
+
 pub mod aidoku;
 pub mod defaults;
 pub mod env;
@@ -7,3 +17,81 @@ pub mod html;
 pub mod json;
 pub mod net;
 pub mod std;
+
+/// Links the functions provided to sources, keeping track of their signatures.
+///
+/// `Linker` doesn't expose the signatures of the functions defined in it, and they're needed
+/// to check the imports of a module without instantiating it.
+pub struct SourceLinker {
+    linker: Linker<WasmStore>,
+    function_types: HashMap<(String, String), FuncType>,
+}
+
+impl SourceLinker {
+    pub fn new(engine: &Engine) -> Self {
+        Self {
+            linker: Linker::new(engine),
+            function_types: HashMap::new(),
+        }
+    }
+
+    /// Defines `func`, which must have the signature given by `ty`.
+    pub fn func_wrap<Params, Args>(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        func: impl IntoFunc<WasmStore, Params, Args>,
+    ) -> Result<&mut Self, LinkerError> {
+        self.linker.func_wrap(module, name, func)?;
+        self.function_types
+            .insert((module.to_owned(), name.to_owned()), ty);
+
+        Ok(self)
+    }
+
+    /// Checks that `module` only imports functions defined here, with the same signatures.
+    pub fn check_imports(&self, module: &Module) -> Result<()> {
+        for import in module.imports() {
+            let function_type = self
+                .function_types
+                .get(&(import.module().to_owned(), import.name().to_owned()));
+
+            match (import.ty(), function_type) {
+                (ExternType::Func(expected_type), Some(function_type))
+                    if expected_type == function_type => {}
+                (ExternType::Func(expected_type), Some(function_type)) => bail!(
+                    "{}.{} is imported as {expected_type:?}, but it's defined as {function_type:?}",
+                    import.module(),
+                    import.name()
+                ),
+                _ => bail!("unknown import {}.{}", import.module(), import.name()),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn into_linker(self) -> Linker<WasmStore> {
+        self.linker
+    }
+}
+
+impl HostFunctionLinker<WasmStore> for SourceLinker {
+    fn func_new(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        func: impl Fn(Caller<'_, WasmStore>, &[Val], &mut [Val]) -> Result<(), wasmi::Error>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Result<&mut Self, LinkerError> {
+        self.linker.func_new(module, name, ty.clone(), func)?;
+        self.function_types
+            .insert((module.to_owned(), name.to_owned()), ty);
+
+        Ok(self)
+    }
+}
@@ -1,19 +1,26 @@
-use crate::{source::wasm_store::Html, util::has_internet_connection};
+use crate::{
+    http::{RequestTimeoutError, RetryPolicy},
+    source::charset,
+};
 use anyhow::{Context, Result};
 use futures::executor;
 use log::warn;
 use num_enum::FromPrimitive;
-use reqwest::{Method, Request};
-use scraper::Html as ScraperHtml;
+use reqwest::{header::CONTENT_TYPE, Client, Method, Request};
 
 use url::Url;
 use wasm_macros::{aidoku_wasm_function, register_wasm_function};
 use wasm_shared::{get_memory, memory_reader::write_bytes};
-use wasmi::{Caller, Linker};
+use wasmi::Caller;
 
-use crate::source::wasm_store::{HTMLElement, RequestState, ResponseData, Value, WasmStore};
+use crate::source::{
+    wasm_imports::SourceLinker,
+    wasm_store::{RequestState, ResponseData, Value, WasmStore},
+};
 
-pub fn register_net_imports(linker: &mut Linker<WasmStore>) -> Result<()> {
+use super::html::parse_document;
+
+pub fn register_net_imports(linker: &mut SourceLinker) -> Result<()> {
     register_wasm_function!(linker, "net", "init", init)?;
     register_wasm_function!(linker, "net", "close", close)?;
     register_wasm_function!(linker, "net", "set_url", set_url)?;
@@ -38,9 +45,6 @@ pub fn register_net_imports(linker: &mut Linker<WasmStore>) -> Result<()> {
     Ok(())
 }
 
-pub const DEFAULT_USER_AGENT: &str =
-    "Mozilla/5.0 (Macintosh; Intel Mac OS X 10.15; rv:107.0) Gecko/20100101 Firefox/107.0";
-
 #[derive(Debug, Default, FromPrimitive)]
 #[repr(u8)]
 enum AidokuHttpMethod {
@@ -84,9 +88,6 @@ fn init(mut caller: Caller<'_, WasmStore>, method: i32) -> Result<i32> {
     };
 
     request.method = Some(method.into());
-    request
-        .headers
-        .insert("User-Agent".into(), DEFAULT_USER_AGENT.into());
 
     Ok(request_descriptor as i32)
 }
@@ -186,29 +187,44 @@ fn set_body(
     Ok(())
 }
 
+// Rate limiting isn't supported yet; we just ignore it instead of failing the source.
 #[aidoku_wasm_function]
-fn set_rate_limit(_caller: Caller<'_, WasmStore>, _rate_limit: i32) -> Result<()> {
-    todo!("rate-limit functions are not supported at the moment")
+fn set_rate_limit(caller: Caller<'_, WasmStore>, rate_limit: i32) -> Result<()> {
+    warn!(
+        "{}: ignoring net.set_rate_limit({rate_limit}), rate limits are not supported",
+        caller.data().id
+    );
+
+    Ok(())
 }
 
 #[aidoku_wasm_function]
-fn set_rate_limit_period(_caller: Caller<'_, WasmStore>, _rate_limit_period: i32) -> Result<()> {
-    todo!("rate-limit functions are not supported at the moment")
+fn set_rate_limit_period(caller: Caller<'_, WasmStore>, rate_limit_period: i32) -> Result<()> {
+    warn!(
+        "{}: ignoring net.set_rate_limit_period({rate_limit_period}), rate limits are not supported",
+        caller.data().id
+    );
+
+    Ok(())
 }
 
 #[aidoku_wasm_function]
 fn send(mut caller: Caller<'_, WasmStore>, request_descriptor_i32: i32) -> Result<()> {
     let wasm_store = caller.data_mut();
     let cancellation_token = wasm_store.context.cancellation_token.clone();
+    // Cloning the client is cheap, and it shares the connection pool with the original one.
+    let client = wasm_store.http_client.clone();
 
     // HACK Before everything, we want to fail fast if no internet connection is available.
     // In theory, it would be easier to just let things fail naturally and move on
     // with our lives; but DNS resolution takes forever (~5s or so) when we have no connection
     // available - due to musl's `getaddrinfo()` call not realizing we have no connection and
     // timing out (EAI_AGAIN). The overhead of checking for a connection here seems worth it.
-    let has_internet_connection =
-        executor::block_on(cancellation_token.run_until_cancelled(has_internet_connection()))
-            .context("failed to check internet connection")?;
+    let has_internet_connection = executor::block_on(
+        cancellation_token
+            .run_until_cancelled(wasm_store.connectivity_checker.has_internet_connection()),
+    )
+    .context("failed to check internet connection")?;
     if !has_internet_connection {
         anyhow::bail!("no internet connection available");
     }
@@ -226,47 +242,91 @@ fn send(mut caller: Caller<'_, WasmStore>, request_descriptor_i32: i32) -> Resul
     }
     .context("request is not in building state")?;
 
-    let client = reqwest::Client::new();
     let request = Request::try_from(request_builder).context("failed to build request")?;
-
-    let warn_cancellation = || {
-        warn!(
-            "request to {:?} was cancelled mid-flight!",
-            &request_builder.url
-        );
+    let retry_policy = RetryPolicy::from_settings(&wasm_store.settings.network);
+    let max_retries = if retry_policy.allows_retrying(request.method()) {
+        retry_policy.max_retries
+    } else {
+        0
     };
 
-    let response =
-        match executor::block_on(cancellation_token.run_until_cancelled(client.execute(request))) {
-            Some(response) => response.context("failed to execute request")?,
-            _ => {
-                warn_cancellation();
+    let mut retry = 0;
+    let response_data = loop {
+        let attempt_request = request.try_clone().context("failed to clone request")?;
+
+        let result = match executor::block_on(
+            cancellation_token.run_until_cancelled(execute_request(&client, attempt_request)),
+        ) {
+            Some(result) => result,
+            None => {
+                warn!("request to {} was cancelled mid-flight!", request.url());
                 anyhow::bail!("request was cancelled mid-flight");
             }
         };
 
-    let response_data = ResponseData {
-        url: response.url().clone(),
-        headers: response.headers().clone(),
-        status_code: response.status(),
-        body: match executor::block_on(cancellation_token.run_until_cancelled(response.bytes())) {
-            Some(bytes) => bytes
-                .context("failed to read response bytes")
-                .map(|bytes| bytes.to_vec())
-                .ok(),
-            _ => {
-                warn_cancellation();
-                anyhow::bail!("request was cancelled mid-flight while reading body");
-            }
-        },
-        bytes_read: 0,
+        let should_retry = retry < max_retries
+            && match &result {
+                Ok(response_data) => retry_policy.should_retry_status(response_data.status_code),
+                Err(e) => retry_policy.should_retry_error(e),
+            };
+
+        if !should_retry {
+            break result;
+        }
+
+        retry += 1;
+        let backoff = retry_policy.backoff(retry);
+        warn!(
+            "request to {} failed, retrying ({retry}/{max_retries}) in {backoff:?}",
+            request.url()
+        );
+
+        if executor::block_on(cancellation_token.run_until_cancelled(tokio::time::sleep(backoff)))
+            .is_none()
+        {
+            anyhow::bail!("request was cancelled while waiting to be retried");
+        }
     };
 
+    let response_data = match response_data {
+        Ok(response_data) => response_data,
+        Err(e) if e.is_timeout() => {
+            let timeout_error = RequestTimeoutError {
+                url: request.url().clone(),
+            };
+            // Sources usually don't report failed requests themselves, so we keep track of the
+            // timeout in order to surface it if the operation ends up failing.
+            wasm_store.context.request_timeout = Some(timeout_error.clone());
+
+            return Err(timeout_error.into());
+        }
+        Err(e) => return Err(e).context("failed to execute request"),
+    };
+
+    let request_state = wasm_store
+        .get_mut_request(request_descriptor)
+        .context("failed to get request state")?;
     *request_state = RequestState::Sent(response_data);
 
     Ok(())
 }
 
+async fn execute_request(client: &Client, request: Request) -> reqwest::Result<ResponseData> {
+    let response = client.execute(request).await?;
+    let url = response.url().clone();
+    let headers = response.headers().clone();
+    let status_code = response.status();
+    let body = response.bytes().await?;
+
+    Ok(ResponseData {
+        url,
+        headers,
+        status_code,
+        body: Some(body.to_vec()),
+        bytes_read: 0,
+    })
+}
+
 #[aidoku_wasm_function]
 fn get_url(mut caller: Caller<'_, WasmStore>, request_descriptor_i32: i32) -> Result<i32> {
     let request_descriptor: usize = request_descriptor_i32
@@ -340,10 +400,9 @@ fn get_data(
     .context("request is not in sent state")?;
 
     let bytes = response.body.as_ref().context("response body not found")?;
-    if response.bytes_read + size >= bytes.len() {
-        let slice = bytes[response.bytes_read..response.bytes_read + size].to_owned();
-
-        response.bytes_read += size;
+    let slice = unread_bytes(bytes, response.bytes_read, size).to_owned();
+    if !slice.is_empty() {
+        response.bytes_read += slice.len();
 
         // FIXME technically we should do this before updating the size, but the
         // borrow checker gets angy >:(
@@ -355,6 +414,13 @@ fn get_data(
     Ok(())
 }
 
+/// Returns up to `size` bytes of `bytes`, starting at `read`, without going past its end.
+fn unread_bytes(bytes: &[u8], read: usize, size: usize) -> &[u8] {
+    let remaining = bytes.get(read..).unwrap_or_default();
+
+    &remaining[..size.min(remaining.len())]
+}
+
 #[aidoku_wasm_function]
 fn get_header(
     mut caller: Caller<'_, WasmStore>,
@@ -426,14 +492,9 @@ fn json(mut caller: Caller<'_, WasmStore>, request_descriptor_i32: i32) -> Resul
     // PERF If we remove the response from the state, we can parse this with ownership of the body,
     // which might enable some optimizations to be done by serde.
     // Check if Aidoku's source allows us to read from the response _after_ we have read it.
-    let value: Value = serde_json::from_slice(
-        response
-            .body
-            .as_ref()
-            .context("response body not found")?
-            .as_slice(),
-    )
-    .context("failed to parse json")?;
+    let body = response.body.as_ref().context("response body not found")?;
+    let json_string = charset::decode_text(body, response_content_type(response));
+    let value: Value = serde_json::from_str(&json_string).context("failed to parse json")?;
 
     Ok(wasm_store.store_std_value(value.into(), None) as i32)
 }
@@ -454,20 +515,31 @@ fn html(mut caller: Caller<'_, WasmStore>, request_descriptor_i32: i32) -> Resul
     }
     .context("request is not in sent state")?;
 
-    // FIXME we should consider the encoding that came on the request
-    let html_string: String =
-        String::from_utf8(response.body.clone().context("response body not found")?)
-            .context("response body is not valid utf-8")?;
-
-    // FIXME this is duplicated from the html module. not sure it's really worth refactoring
-    // but here's a note
-    let document = ScraperHtml::parse_document(&html_string);
-    let node_id = document.root_element().id();
-    let html_element = HTMLElement {
-        document: Html::from(document).into(),
-        node_id,
-        base_uri: response.url.clone().into(),
-    };
+    let body = response.body.as_ref().context("response body not found")?;
+    let html_string = charset::decode_html(body, response_content_type(response));
+    let html_element = parse_document(&html_string, Some(response.url.clone()));
 
     Ok(wasm_store.store_std_value(Value::from(vec![html_element]).into(), None) as i32)
 }
+
+fn response_content_type(response: &ResponseData) -> Option<&str> {
+    response
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_clamps_reads_to_the_remaining_bytes() {
+        let bytes = b"hello world";
+
+        assert_eq!(unread_bytes(bytes, 0, 5), b"hello");
+        assert_eq!(unread_bytes(bytes, 6, 100), b"world");
+        assert_eq!(unread_bytes(bytes, 11, 1), b"");
+        assert_eq!(unread_bytes(bytes, 20, 1), b"");
+    }
+}
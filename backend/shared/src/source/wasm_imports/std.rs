@@ -9,10 +9,11 @@ use wasm_shared::{
     get_memory,
     memory_reader::{read_string as read_memory_string, write_bytes},
 };
-use wasmi::{core::F64, Caller, Linker};
+use wasmi::{core::F64, Caller};
 
 use crate::source::{
     model::{Filter, FilterType, Manga, MangaPageResult},
+    wasm_imports::SourceLinker,
     wasm_store::{ObjectValue, Value, ValueMap, ValueRef, WasmStore},
 };
 
@@ -34,9 +35,10 @@ trait FieldAsValue {
     fn field_as_value(&self, field: &str) -> Option<Value>;
 }
 
-pub fn register_std_imports(linker: &mut Linker<WasmStore>) -> Result<()> {
+pub fn register_std_imports(linker: &mut SourceLinker) -> Result<()> {
     register_wasm_function!(linker, "std", "copy", copy)?;
     register_wasm_function!(linker, "std", "destroy", destroy)?;
+    register_wasm_function!(linker, "std", "retain", retain)?;
     register_wasm_function!(linker, "std", "create_null", create_null)?;
     register_wasm_function!(linker, "std", "create_int", create_int)?;
     register_wasm_function!(linker, "std", "create_float", create_float)?;
@@ -88,6 +90,22 @@ fn destroy(mut caller: Caller<'_, WasmStore>, descriptor_i32: i32) -> Result<()>
     Ok(())
 }
 
+// Not part of the Aidoku API: for sources importing this function, values created while running a
+// source function are freed once it returns, so they need to retain the values they want to keep
+// around between calls.
+#[aidoku_wasm_function]
+fn retain(mut caller: Caller<'_, WasmStore>, descriptor_i32: i32) -> Result<i32> {
+    let descriptor: usize = descriptor_i32
+        .try_into()
+        .context("failed to convert descriptor_i32 in retain")?;
+
+    if !caller.data_mut().retain_std_value(descriptor) {
+        anyhow::bail!("failed to get value in retain");
+    }
+
+    Ok(0)
+}
+
 #[aidoku_wasm_function]
 fn create_null(caller: Caller<'_, WasmStore>) -> Result<i32> {
     Ok(create_value(caller, Value::Null))
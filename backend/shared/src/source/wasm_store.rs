@@ -1,5 +1,8 @@
 use pared::sync::Parc;
-use std::collections::{BTreeMap, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 use tokio_util::sync::CancellationToken;
 
 use anyhow::anyhow;
@@ -12,10 +15,16 @@ use reqwest::{
     Method, Request, StatusCode, Url,
 };
 use scraper::{ElementRef, Html as ScraperHtml};
+use wasmi::{StoreLimits, StoreLimitsBuilder};
 
-use crate::settings::{Settings, SourceSettingValue};
+use crate::{
+    http::{self, RequestTimeoutError},
+    settings::{Settings, SourceSettingValue},
+    util::ConnectivityChecker,
+};
 
 use super::{
+    cookie_jar::SourceCookieJar,
     model::{Chapter, DeepLink, Filter, Manga, MangaPageResult, Page},
     source_settings::SourceSettings,
 };
@@ -40,7 +49,7 @@ pub enum ObjectValue {
     Filter(Filter),
 }
 
-#[derive(From, Deref, Debug)]
+#[derive(From, Deref, Debug, Clone)]
 pub struct Html(ScraperHtml);
 
 // FIXME THIS IS BORKED AS FUCK
@@ -54,10 +63,84 @@ pub struct HTMLElement {
     pub base_uri: Option<Url>,
 }
 
+impl Html {
+    pub fn into_inner(self) -> ScraperHtml {
+        self.0
+    }
+}
+
 impl HTMLElement {
     pub fn element_ref(&self) -> ElementRef {
         ElementRef::wrap(self.document.tree.get(self.node_id).unwrap()).unwrap()
     }
+
+    pub fn belongs_to(&self, document: &Parc<Html>) -> bool {
+        std::ptr::eq::<Html>(&*self.document, &**document)
+    }
+}
+
+/// Returns a copy of `value` with its HTML elements from `document` moved to `new_document`, or
+/// `None` if it has no elements from `document`.
+fn replace_html_document_in_value(
+    value: &Value,
+    document: &Parc<Html>,
+    new_document: &Parc<Html>,
+) -> Option<Value> {
+    match value {
+        Value::HTMLElements(elements) if elements.iter().any(|e| e.belongs_to(document)) => {
+            let elements = elements
+                .iter()
+                .map(|element| HTMLElement {
+                    document: if element.belongs_to(document) {
+                        new_document.clone()
+                    } else {
+                        element.document.clone()
+                    },
+                    ..element.clone()
+                })
+                .collect();
+
+            Some(Value::HTMLElements(elements))
+        }
+        Value::Array(values) => {
+            let new_values: Vec<Option<Value>> = values
+                .iter()
+                .map(|value| replace_html_document_in_value(value, document, new_document))
+                .collect();
+
+            if new_values.iter().all(Option::is_none) {
+                return None;
+            }
+
+            let values = values
+                .iter()
+                .zip(new_values)
+                .map(|(value, new_value)| new_value.unwrap_or_else(|| value.clone()))
+                .collect();
+
+            Some(Value::Array(values))
+        }
+        _ => None,
+    }
+}
+
+/// Collects the addresses of the HTML documents referenced by `value` into `documents`.
+fn collect_html_documents(value: &Value, documents: &mut HashSet<*const Html>) {
+    match value {
+        Value::HTMLElements(elements) => {
+            documents.extend(
+                elements
+                    .iter()
+                    .map(|element| &*element.document as *const Html),
+            );
+        }
+        Value::Array(values) => {
+            for value in values {
+                collect_html_documents(value, documents);
+            }
+        }
+        _ => {}
+    }
 }
 
 #[derive(Debug, Clone, From, TryUnwrap)]
@@ -123,6 +206,38 @@ pub enum OperationContextObject {
 pub struct OperationContext {
     pub cancellation_token: CancellationToken,
     pub current_object: OperationContextObject,
+    /// Set when a request made during the operation timed out.
+    pub request_timeout: Option<RequestTimeoutError>,
+}
+
+/// The state of a `WasmStore` at some point, used to free everything created after it.
+#[derive(Debug, Clone, Copy)]
+pub struct StoreCheckpoint {
+    std_descriptor_pointer: Option<usize>,
+    requests: usize,
+}
+
+/// How much is currently being kept alive by a source.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StoreMetrics {
+    pub std_descriptors: usize,
+    pub retained_std_descriptors: usize,
+    pub html_documents: usize,
+    pub requests: usize,
+}
+
+impl std::ops::Add for StoreMetrics {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            std_descriptors: self.std_descriptors + other.std_descriptors,
+            retained_std_descriptors: self.retained_std_descriptors
+                + other.retained_std_descriptors,
+            html_documents: self.html_documents + other.html_documents,
+            requests: self.requests + other.requests,
+        }
+    }
 }
 
 #[derive(Default, Debug)]
@@ -133,20 +248,56 @@ pub struct WasmStore {
     // FIXME this probably should be source-specific, and not a copy of all settigns
     // we do rely on the `languages` global setting right now, so maybe this is really needed? idk
     pub settings: Settings,
+    /// Client used for all requests made by the source, so that connections can be reused.
+    pub http_client: reqwest::Client,
+    pub cookie_jar: Arc<SourceCookieJar>,
+    pub connectivity_checker: Arc<ConnectivityChecker>,
+    /// Limits on the memory the source can allocate.
+    pub limits: StoreLimits,
+    /// Whether values are freed once the operation that created them finishes. Only sources
+    /// importing `std.retain` know about this, so it can't be done for the other ones without
+    /// invalidating the descriptors they keep between calls.
+    pub reclaims_std_values: bool,
+    /// Headers (e.g. the User-Agent) added to every request made by the source.
+    default_request_headers: HashMap<String, String>,
     std_descriptor_pointer: Option<usize>,
     std_descriptors: HashMap<usize, ValueRef>,
     std_references: HashMap<usize, Vec<usize>>,
+    /// Descriptors that should survive the end of the operation that created them.
+    retained_std_descriptors: HashSet<usize>,
     requests: Vec<RequestState>,
 }
 
 impl WasmStore {
-    pub fn new(id: String, source_settings: SourceSettings, settings: Settings) -> Self {
-        Self {
+    pub fn new(
+        id: String,
+        source_settings: SourceSettings,
+        settings: Settings,
+        cookie_jar: Arc<SourceCookieJar>,
+        connectivity_checker: Arc<ConnectivityChecker>,
+    ) -> anyhow::Result<Self> {
+        let http_client = http::client_builder(&settings.network)?
+            .cookie_provider(cookie_jar.clone())
+            .build()?;
+        let default_request_headers = settings.default_request_headers(&id);
+        // Trapping makes a source hitting the limit fail right away (with a clear error),
+        // instead of letting it deal with the failed allocation.
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(settings.source_limits.max_memory_bytes())
+            .trap_on_grow_failure(true)
+            .build();
+
+        Ok(Self {
             id,
             source_settings,
             settings,
+            http_client,
+            cookie_jar,
+            connectivity_checker,
+            limits,
+            default_request_headers,
             ..Default::default()
-        }
+        })
     }
 
     pub fn get_std_value(&self, descriptor: usize) -> Option<ValueRef> {
@@ -170,6 +321,79 @@ impl WasmStore {
 
     pub fn remove_std_value(&mut self, descriptor: usize) {
         self.std_descriptors.remove(&descriptor);
+        self.std_references.remove(&descriptor);
+        self.retained_std_descriptors.remove(&descriptor);
+    }
+
+    /// Keeps `descriptor` from being freed by `reclaim_since`. Returns `false` if there's no
+    /// value stored with the descriptor.
+    pub fn retain_std_value(&mut self, descriptor: usize) -> bool {
+        if !self.std_descriptors.contains_key(&descriptor) {
+            return false;
+        }
+
+        self.retained_std_descriptors.insert(descriptor);
+
+        true
+    }
+
+    pub fn checkpoint(&self) -> StoreCheckpoint {
+        StoreCheckpoint {
+            std_descriptor_pointer: self.std_descriptor_pointer,
+            requests: self.requests.len(),
+        }
+    }
+
+    /// Frees every descriptor and request created after `checkpoint`, except for the retained
+    /// descriptors. Does nothing unless the source opted into it (see `reclaims_std_values`).
+    ///
+    /// Sources rarely destroy the values they create, so without this everything they ever
+    /// created (including whole HTML documents) would be kept alive.
+    pub fn reclaim_since(&mut self, checkpoint: StoreCheckpoint) {
+        if !self.reclaims_std_values {
+            return;
+        }
+
+        let retained_std_descriptors = &self.retained_std_descriptors;
+        let is_reclaimable = |descriptor: &usize| {
+            checkpoint
+                .std_descriptor_pointer
+                .is_none_or(|pointer| *descriptor > pointer)
+                && !retained_std_descriptors.contains(descriptor)
+        };
+
+        self.std_descriptors
+            .retain(|descriptor, _| !is_reclaimable(descriptor));
+        self.std_references
+            .retain(|descriptor, _| !is_reclaimable(descriptor));
+        self.requests.truncate(checkpoint.requests);
+    }
+
+    pub fn metrics(&self) -> StoreMetrics {
+        let mut html_documents = HashSet::new();
+        for value in self.std_descriptors.values() {
+            collect_html_documents(value, &mut html_documents);
+        }
+
+        StoreMetrics {
+            std_descriptors: self.std_descriptors.len(),
+            retained_std_descriptors: self.retained_std_descriptors.len(),
+            html_documents: html_documents.len(),
+            requests: self.requests.len(),
+        }
+    }
+
+    /// Makes every stored HTML element pointing to `document` point to `new_document` instead.
+    ///
+    /// Documents are shared between all elements selected from them, so this is how changes to a
+    /// document become visible through every descriptor referencing it.
+    pub fn replace_html_document(&mut self, document: &Parc<Html>, new_document: Parc<Html>) {
+        for value in self.std_descriptors.values_mut() {
+            if let Some(new_value) = replace_html_document_in_value(value, document, &new_document)
+            {
+                *value = new_value.into();
+            }
+        }
     }
 
     // This might be used by some Aidoku unimplemented functions
@@ -182,7 +406,10 @@ impl WasmStore {
 
     // TODO change this into a request descriptor
     pub fn create_request(&mut self) -> usize {
-        let new_request_state = RequestState::Building(RequestBuildingState::default());
+        let new_request_state = RequestState::Building(RequestBuildingState {
+            headers: self.default_request_headers.clone(),
+            ..Default::default()
+        });
         self.requests.push(new_request_state);
 
         self.requests.len() - 1
@@ -292,3 +519,42 @@ impl From<SourceSettingValue> for Value {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reclaims_unretained_values_created_after_a_checkpoint() {
+        let mut store = WasmStore {
+            reclaims_std_values: true,
+            ..Default::default()
+        };
+        let existing = store.store_std_value(Value::Int(1).into(), None);
+
+        let checkpoint = store.checkpoint();
+        let created = store.store_std_value(Value::Int(2).into(), None);
+        let retained = store.store_std_value(Value::Int(3).into(), None);
+        assert!(store.retain_std_value(retained));
+        store.create_request();
+
+        store.reclaim_since(checkpoint);
+
+        assert!(store.get_std_value(existing).is_some());
+        assert!(store.get_std_value(created).is_none());
+        assert!(store.get_std_value(retained).is_some());
+        assert_eq!(store.metrics().requests, 0);
+    }
+
+    #[test]
+    fn it_keeps_values_of_sources_not_opting_into_reclaiming() {
+        let mut store = WasmStore::default();
+
+        let checkpoint = store.checkpoint();
+        let created = store.store_std_value(Value::Int(1).into(), None);
+
+        store.reclaim_since(checkpoint);
+
+        assert!(store.get_std_value(created).is_some());
+    }
+}
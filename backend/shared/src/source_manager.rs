@@ -1,42 +1,95 @@
 use std::{
     collections::HashMap,
     fs,
+    io::Write,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::{Context, Result};
+use log::warn;
+use tempfile::NamedTempFile;
 
 use crate::{
-    model::SourceId, settings::Settings, source::Source, source_collection::SourceCollection,
+    model::SourceId,
+    settings::Settings,
+    source::{cookie_jar::SourceCookieJar, Source, SourceManifest},
+    source_collection::SourceCollection,
+    util::ConnectivityChecker,
 };
 
+/// A source file that is installed, but couldn't be loaded.
+#[derive(Debug, Clone)]
+pub struct FailedSource {
+    pub id: SourceId,
+    pub error: String,
+}
+
 pub struct SourceManager {
     sources_folder: PathBuf,
     sources_by_id: HashMap<SourceId, Source>,
+    failed_sources_by_id: HashMap<SourceId, FailedSource>,
     settings: Settings,
+    connectivity_checker: Arc<ConnectivityChecker>,
 }
 
 impl SourceManager {
     pub fn from_folder(path: PathBuf, settings: Settings) -> Result<Self> {
         fs::create_dir_all(&path).context("while trying to ensure sources folder exists")?;
-        let sources_by_id =
-            Self::load_all_sources(&path, &settings).context("couldn't load sources")?;
+        let connectivity_checker = Arc::new(ConnectivityChecker::new(&settings.network));
+        let (sources_by_id, failed_sources_by_id) =
+            Self::load_all_sources(&path, &settings, &connectivity_checker)
+                .context("couldn't load sources")?;
 
         Ok(Self {
             sources_folder: path,
             sources_by_id,
+            failed_sources_by_id,
             settings,
+            connectivity_checker,
         })
     }
 
     pub fn install_source(&mut self, id: &SourceId, contents: impl AsRef<[u8]>) -> Result<()> {
         let target_path = self.source_path(id);
-        fs::write(&target_path, contents)?;
 
-        self.sources_by_id.insert(
-            id.clone(),
-            Source::from_aix_file(&target_path, self.settings.clone())?,
-        );
+        // Check the new file before replacing the installed one, so that a broken download
+        // doesn't take down a working source. The temporary file doesn't have the `.aix`
+        // extension, so it's never picked up as a source if we're interrupted.
+        let mut new_file = NamedTempFile::new_in(&self.sources_folder)
+            .context("couldn't create a temporary file for the source")?;
+        new_file.write_all(contents.as_ref())?;
+        Source::validate_aix_file(new_file.path())
+            .with_context(|| format!("the downloaded source {} is invalid", id.value()))?;
+        new_file
+            .persist(&target_path)
+            .with_context(|| format!("couldn't write source to {}", target_path.display()))?;
+
+        // Whatever was installed before is gone now, so it shouldn't be used even if the new
+        // version fails to load.
+        self.sources_by_id.remove(id);
+        self.failed_sources_by_id.remove(id);
+
+        let source = match Source::from_aix_file(
+            &target_path,
+            self.settings.clone(),
+            self.connectivity_checker.clone(),
+        ) {
+            Ok(source) => source,
+            Err(e) => {
+                self.failed_sources_by_id.insert(
+                    id.clone(),
+                    FailedSource {
+                        id: id.clone(),
+                        error: format!("{e:#}"),
+                    },
+                );
+
+                return Err(e);
+            }
+        };
+
+        self.sources_by_id.insert(id.clone(), source);
 
         Ok(())
     }
@@ -45,19 +98,51 @@ impl SourceManager {
         let source_path = self.source_path(id);
         fs::remove_file(&source_path)?;
 
-        self.sources_by_id.remove(&id.clone());
+        let cookies_path = SourceCookieJar::path_for_source(&source_path);
+        if cookies_path.exists() {
+            fs::remove_file(&cookies_path)?;
+        }
+
+        self.sources_by_id.remove(id);
+        self.failed_sources_by_id.remove(id);
 
         Ok(())
     }
 
-    pub fn update_settings(&mut self, settings: Settings) -> Result<()> {
-        self.sources_by_id = Self::load_all_sources(&self.sources_folder, &settings)?;
+    pub fn update_settings(&mut self, settings: Settings) {
+        // Keep the cached connectivity check around, unless it might not hold anymore.
+        if settings.network != self.settings.network {
+            self.connectivity_checker = Arc::new(ConnectivityChecker::new(&settings.network));
+        }
+
+        for source in self.sources_by_id.values() {
+            source.update_settings(settings.clone(), self.connectivity_checker.clone());
+        }
+
         self.settings = settings;
+    }
 
-        Ok(())
+    /// Like `update_settings`, but for when only the settings of a single source changed.
+    pub fn update_source_settings(&mut self, id: &SourceId, settings: Settings) {
+        if let Some(source) = self.sources_by_id.get(id) {
+            source.update_settings(settings.clone(), self.connectivity_checker.clone());
+        }
+
+        self.settings = settings;
+    }
+
+    /// Sources which are installed, but couldn't be loaded.
+    pub fn failed_sources(&self) -> Vec<&FailedSource> {
+        self.failed_sources_by_id.values().collect()
     }
 
-    fn load_all_sources(path: &Path, settings: &Settings) -> Result<HashMap<SourceId, Source>> {
+    /// Loads every source inside `path`. Sources are loaded independently, so that a single
+    /// broken source file doesn't prevent the others from being used.
+    fn load_all_sources(
+        path: &Path,
+        settings: &Settings,
+        connectivity_checker: &Arc<ConnectivityChecker>,
+    ) -> Result<(HashMap<SourceId, Source>, HashMap<SourceId, FailedSource>)> {
         let files = fs::read_dir(path).with_context(|| {
             format!(
                 "while attempting to read source collection at {}",
@@ -65,21 +150,45 @@ impl SourceManager {
             )
         })?;
 
-        let sources: Vec<Source> = files
+        let paths = files
             .filter_map(|entry| entry.ok().map(|entry| entry.path()))
             .filter(|path| {
                 path.extension()
                     .is_some_and(|ext| ext.eq_ignore_ascii_case("aix"))
-            })
-            .map(|path| Source::from_aix_file(&path, settings.clone()))
-            .collect::<Result<_>>()?;
-
-        let sources_by_id = sources
-            .into_iter()
-            .map(|source| (SourceId::new(source.manifest().info.id.clone()), source))
-            .collect();
-
-        Ok(sources_by_id)
+            });
+
+        let mut sources_by_id = HashMap::new();
+        let mut failed_sources_by_id = HashMap::new();
+        for path in paths {
+            match Source::from_aix_file(&path, settings.clone(), connectivity_checker.clone()) {
+                Ok(source) => {
+                    let id = SourceId::new(source.manifest().info.id.clone());
+                    sources_by_id.insert(id, source);
+                }
+                Err(e) => {
+                    warn!("couldn't load source at {}: {e:?}", path.display());
+
+                    // The source may have failed after its manifest was read. Otherwise, since
+                    // sources are installed as `<id>.aix`, the file name is our best guess.
+                    let id = match SourceManifest::from_aix_file(&path) {
+                        Ok(manifest) => SourceId::new(manifest.info.id),
+                        Err(_) => SourceId::new(
+                            path.file_stem()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                                .into_owned(),
+                        ),
+                    };
+                    let failed_source = FailedSource {
+                        id: id.clone(),
+                        error: format!("{e:#}"),
+                    };
+                    failed_sources_by_id.insert(id, failed_source);
+                }
+            }
+        }
+
+        Ok((sources_by_id, failed_sources_by_id))
     }
 
     fn source_path(&self, id: &SourceId) -> PathBuf {
@@ -96,3 +205,46 @@ impl SourceCollection for SourceManager {
         self.sources_by_id.values().collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tempfile::tempdir;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn source_file(version: usize) -> Result<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("Payload/source.json", FileOptions::default())?;
+        write!(
+            writer,
+            r#"{{"info":{{"id":"en.test","lang":"en","name":"Test","version":{version}}}}}"#
+        )?;
+        writer.start_file("Payload/main.wasm", FileOptions::default())?;
+        writer.write_all(b"\0asm\x01\0\0\0")?;
+
+        Ok(writer.finish()?.into_inner())
+    }
+
+    #[test]
+    fn it_keeps_the_installed_source_when_the_new_one_is_invalid() -> Result<()> {
+        let folder = tempdir()?;
+        let mut source_manager =
+            SourceManager::from_folder(folder.path().to_owned(), Settings::default())?;
+        let id = SourceId::new("en.test".to_string());
+
+        source_manager.install_source(&id, source_file(1)?)?;
+        assert!(source_manager
+            .install_source(&id, b"not a source archive")
+            .is_err());
+
+        let source = source_manager.get_by_id(&id).unwrap();
+        assert_eq!(source.manifest().info.version, 1);
+        assert!(source_manager.failed_sources().is_empty());
+        assert_eq!(fs::read_dir(folder.path())?.count(), 1);
+
+        Ok(())
+    }
+}
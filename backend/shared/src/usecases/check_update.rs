@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use anyhow::Context;
+use reqwest::Client;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
@@ -20,11 +21,13 @@ struct GitHubRelease {
     html_url: String,
 }
 
-pub async fn check_update(current_version: String) -> anyhow::Result<CheckUpdateResponse> {
+pub async fn check_update(
+    client: &Client,
+    current_version: String,
+) -> anyhow::Result<CheckUpdateResponse> {
     let current_version = Version::parse(current_version.trim_start_matches("v"))
         .context("Failed to parse current version")?;
     // Get latest release from GitHub API
-    let client = reqwest::Client::new();
     let response = client
         .get("https://api.github.com/repos/hanatsumi/rakuyomi/releases/latest")
         .header("User-Agent", "rakuyomi")
@@ -0,0 +1,7 @@
+use anyhow::Result;
+
+use crate::source::Source;
+
+pub fn clear_source_cookies(source: &Source) -> Result<()> {
+    source.cookie_jar().clear()
+}
@@ -1,18 +1,21 @@
 use std::path::PathBuf;
 
 use crate::{
-    chapter_downloader::ensure_chapter_is_in_storage,
-    chapter_downloader::Error as ChapterDownloaderError, chapter_storage::ChapterStorage,
-    model::ChapterId, source::Source,
+    chapter_downloader::{ChapterDownloader, Error as ChapterDownloaderError},
+    chapter_storage::ChapterStorage,
+    model::ChapterId,
+    source::Source,
 };
 
 pub async fn fetch_manga_chapter(
     source: &Source,
     chapter_storage: &ChapterStorage,
+    chapter_downloader: &ChapterDownloader,
     chapter_id: &ChapterId,
     chapter_num: Option<f64>,
 ) -> Result<PathBuf, Error> {
-    ensure_chapter_is_in_storage(chapter_storage, source, chapter_id, chapter_num)
+    chapter_downloader
+        .ensure_chapter_is_in_storage(chapter_storage, source, chapter_id, chapter_num)
         .await
         .map_err(|e| match e {
             ChapterDownloaderError::DownloadError(e) => Error::DownloadError(e),
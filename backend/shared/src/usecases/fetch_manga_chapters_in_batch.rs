@@ -6,8 +6,7 @@ use tokio::select;
 use tokio_util::sync::CancellationToken;
 
 use crate::{
-    chapter_downloader::ensure_chapter_is_in_storage,
-    chapter_downloader::Error as ChapterDownloaderError,
+    chapter_downloader::{ChapterDownloader, Error as ChapterDownloaderError},
     chapter_storage::ChapterStorage,
     database::Database,
     model::{ChapterInformation, MangaId},
@@ -19,6 +18,7 @@ pub fn fetch_manga_chapters_in_batch<'a>(
     source: &'a Source,
     db: &'a Database,
     chapter_storage: &'a ChapterStorage,
+    chapter_downloader: &'a ChapterDownloader,
     id: MangaId,
     filter: Filter,
 ) -> impl Stream<Item = ProgressReport> + 'a {
@@ -36,7 +36,7 @@ pub fn fetch_manga_chapters_in_batch<'a>(
 
                     return;
                 },
-                result = ensure_chapter_is_in_storage(
+                result = chapter_downloader.ensure_chapter_is_in_storage(
                     chapter_storage,
                     source,
                     &information.id,
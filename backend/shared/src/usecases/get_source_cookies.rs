@@ -0,0 +1,5 @@
+use crate::source::{cookie_jar::StoredCookie, Source};
+
+pub fn get_source_cookies(source: &Source) -> Vec<StoredCookie> {
+    source.cookie_jar().cookies()
+}
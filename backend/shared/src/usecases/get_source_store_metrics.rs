@@ -0,0 +1,5 @@
+use crate::source::{Source, StoreMetrics};
+
+pub fn get_source_store_metrics(source: &Source) -> StoreMetrics {
+    source.store_metrics()
+}
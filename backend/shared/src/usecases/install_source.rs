@@ -1,49 +1,47 @@
-use anyhow::{anyhow, Result};
-use futures::{stream, StreamExt, TryStreamExt};
-use serde::Deserialize;
+use anyhow::{anyhow, Context, Result};
+use reqwest::Client;
 use url::Url;
 
 use crate::{model::SourceId, source_manager::SourceManager};
 
+use super::list_available_sources::{fetch_available_sources, AvailableSource};
+
 pub async fn install_source(
+    client: &Client,
     source_manager: &mut SourceManager,
-    source_lists: &Vec<Url>,
+    source_lists: &[Url],
     source_id: SourceId,
 ) -> Result<()> {
-    let (source_list, source_list_item) = stream::iter(source_lists)
-        .then(|source_list| async move {
-            let source_list_items = reqwest::get(source_list.clone())
-                .await?
-                .json::<Vec<SourceListItem>>()
-                .await?;
-
-            anyhow::Ok((source_list, source_list_items))
-        })
-        .try_collect::<Vec<_>>()
+    // The same source might be present in multiple source lists, so we pick the newest.
+    let available_source = fetch_available_sources(client, source_lists.to_vec())
         .await?
         .into_iter()
-        .flat_map(|(source_list, items)| {
-            items
-                .into_iter()
-                .map(|item| (source_list.clone(), item))
-                .collect::<Vec<_>>()
-        })
-        .find(|(_, item)| item.id == source_id)
+        .filter(|available_source| available_source.information.id == source_id)
+        .max_by_key(|available_source| available_source.information.version)
         .ok_or_else(|| anyhow!("couldn't find source with id '{:?}'", source_id))?;
 
-    let aix_url = source_list
-        .join(&format!("sources/{}", &source_list_item.file))
-        .unwrap();
-
-    let aix_content = reqwest::get(aix_url).await?.bytes().await?;
+    let aix_content = fetch_source_file(client, &available_source).await?;
 
     source_manager.install_source(&source_id, aix_content)?;
 
     Ok(())
 }
 
-#[derive(Deserialize)]
-struct SourceListItem {
-    id: SourceId,
-    file: String,
+/// Downloads the `.aix` file of a source from its source list.
+pub async fn fetch_source_file(
+    client: &Client,
+    available_source: &AvailableSource,
+) -> Result<Vec<u8>> {
+    let aix_url = &available_source.aix_url;
+    let aix_content = client
+        .get(aix_url.clone())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to download source file at {aix_url}"))?
+        .bytes()
+        .await
+        .with_context(|| format!("failed to download source file at {aix_url}"))?;
+
+    Ok(aix_content.to_vec())
 }
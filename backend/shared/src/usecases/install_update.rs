@@ -1,6 +1,7 @@
 use anyhow::Context;
 use futures_util::StreamExt;
 use log::{error, info, warn};
+use reqwest::Client;
 use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
@@ -8,9 +9,13 @@ use std::time::Duration;
 use tempfile::{NamedTempFile, TempDir};
 use walkdir::WalkDir;
 
-pub async fn install_update(version: String, build_name: String) -> anyhow::Result<()> {
+pub async fn install_update(
+    client: &Client,
+    version: String,
+    build_name: String,
+) -> anyhow::Result<()> {
     // Download the asset to a temporary file
-    let update_zip_file = download_update_zip(&version, &build_name).await?;
+    let update_zip_file = download_update_zip(client, &version, &build_name).await?;
 
     // Get plugin directory (parent of the executable)
     let current_exe = std::env::current_exe().context("Could not get current executable")?;
@@ -29,8 +34,11 @@ pub async fn install_update(version: String, build_name: String) -> anyhow::Resu
 }
 
 /// Downloads the update zip file and saves it to a temporary file.
-async fn download_update_zip(version: &str, build_name: &str) -> anyhow::Result<NamedTempFile> {
-    let client = reqwest::Client::new();
+async fn download_update_zip(
+    client: &Client,
+    version: &str,
+    build_name: &str,
+) -> anyhow::Result<NamedTempFile> {
     let asset_name = format!("rakuyomi-{}.zip", build_name);
     let url = format!(
         "https://github.com/hanatsumi/rakuyomi/releases/download/v{}/{}",
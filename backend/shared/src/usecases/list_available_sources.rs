@@ -1,22 +1,67 @@
 use anyhow::{Context, Result};
 use futures::{stream, StreamExt, TryStreamExt};
+use reqwest::Client;
+use serde::Deserialize;
 use url::Url;
 
 use crate::model::SourceInformation;
 
-pub async fn list_available_sources(source_lists: Vec<Url>) -> Result<Vec<SourceInformation>> {
-    let mut source_informations: Vec<SourceInformation> = stream::iter(source_lists)
+/// A source listed in a source list.
+#[derive(Clone, Debug)]
+pub struct AvailableSource {
+    pub information: SourceInformation,
+    /// Where the `.aix` file of the source can be downloaded from.
+    pub aix_url: Url,
+}
+
+pub async fn list_available_sources(
+    client: &Client,
+    source_lists: Vec<Url>,
+) -> Result<Vec<SourceInformation>> {
+    let mut source_informations: Vec<SourceInformation> =
+        fetch_available_sources(client, source_lists)
+            .await?
+            .into_iter()
+            .map(|available_source| available_source.information)
+            .collect();
+
+    source_informations.sort_by_key(|source| source.name.clone());
+
+    Ok(source_informations)
+}
+
+/// Fetches every source list, returning the sources in them in the order they're listed.
+pub async fn fetch_available_sources(
+    client: &Client,
+    source_lists: Vec<Url>,
+) -> Result<Vec<AvailableSource>> {
+    let available_sources = stream::iter(source_lists)
         .then(|source_list| async move {
-            let response = reqwest::get(source_list.clone())
+            let response = client
+                .get(source_list.clone())
+                .send()
                 .await
+                .and_then(|response| response.error_for_status())
                 .with_context(|| format!("failed to fetch source list at {}", &source_list))?;
 
-            let source_informations = response
-                .json::<Vec<SourceInformation>>()
+            let source_list_items = response
+                .json::<Vec<SourceListItem>>()
                 .await
                 .with_context(|| format!("failed to parse source list at {}", &source_list))?;
 
-            anyhow::Ok(source_informations)
+            source_list_items
+                .into_iter()
+                .map(|item| {
+                    let aix_url = source_list
+                        .join(&format!("sources/{}", &item.file))
+                        .with_context(|| format!("invalid source file '{}'", &item.file))?;
+
+                    Ok(AvailableSource {
+                        information: item.information,
+                        aix_url,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
         })
         .try_collect::<Vec<_>>()
         .await?
@@ -24,7 +69,12 @@ pub async fn list_available_sources(source_lists: Vec<Url>) -> Result<Vec<Source
         .flatten()
         .collect();
 
-    source_informations.sort_by_key(|source| source.name.clone());
+    Ok(available_sources)
+}
 
-    Ok(source_informations)
+#[derive(Deserialize)]
+struct SourceListItem {
+    #[serde(flatten)]
+    information: SourceInformation,
+    file: String,
 }
@@ -1,13 +1,39 @@
-use crate::{model::SourceInformation, source_collection::SourceCollection};
+use crate::{
+    model::SourceInformation, source_collection::SourceCollection, source_manager::SourceManager,
+};
 
-pub fn list_installed_sources(source_collection: &impl SourceCollection) -> Vec<SourceInformation> {
-    let mut source_informations: Vec<SourceInformation> = source_collection
+pub struct InstalledSource {
+    pub information: SourceInformation,
+    /// Why the source couldn't be loaded, if it couldn't. Those sources can't be used until
+    /// they're reinstalled.
+    pub load_error: Option<String>,
+}
+
+pub fn list_installed_sources(source_manager: &SourceManager) -> Vec<InstalledSource> {
+    let loaded_sources = source_manager
         .sources()
         .into_iter()
-        .map(|source| source.manifest().into())
-        .collect();
+        .map(|source| InstalledSource {
+            information: source.manifest().into(),
+            load_error: None,
+        });
+
+    let failed_sources = source_manager
+        .failed_sources()
+        .into_iter()
+        .map(|failed_source| InstalledSource {
+            information: SourceInformation {
+                id: failed_source.id.clone(),
+                name: failed_source.id.value().clone(),
+                version: 0,
+            },
+            load_error: Some(failed_source.error.clone()),
+        });
+
+    let mut installed_sources: Vec<InstalledSource> =
+        loaded_sources.chain(failed_sources).collect();
 
-    source_informations.sort_by_key(|source| source.name.clone());
+    installed_sources.sort_by_key(|source| source.information.name.clone());
 
-    source_informations
+    installed_sources
 }
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use reqwest::Client;
+use url::Url;
+
+use crate::model::SourceInformation;
+
+use super::list_available_sources::{fetch_available_sources, AvailableSource};
+
+#[derive(Clone, Debug)]
+pub struct SourceUpdate {
+    pub installed: SourceInformation,
+    pub available: AvailableSource,
+}
+
+/// Lists the installed sources which have a newer version available in the source lists.
+pub async fn list_source_updates(
+    client: &Client,
+    source_lists: Vec<Url>,
+    installed_sources: Vec<SourceInformation>,
+) -> Result<Vec<SourceUpdate>> {
+    let available_sources = fetch_available_sources(client, source_lists).await?;
+
+    Ok(find_source_updates(installed_sources, &available_sources))
+}
+
+fn find_source_updates(
+    installed_sources: Vec<SourceInformation>,
+    available_sources: &[AvailableSource],
+) -> Vec<SourceUpdate> {
+    installed_sources
+        .into_iter()
+        .filter_map(|installed| {
+            // The same source might be present in multiple source lists, so we pick the newest.
+            let available = available_sources
+                .iter()
+                .filter(|available| available.information.id == installed.id)
+                .max_by_key(|available| available.information.version)?
+                .clone();
+
+            (available.information.version > installed.version).then_some(SourceUpdate {
+                installed,
+                available,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use crate::model::SourceId;
+
+    use super::*;
+
+    fn source_information(id: &str, version: usize) -> SourceInformation {
+        SourceInformation {
+            id: SourceId::new(id.to_string()),
+            name: id.to_string(),
+            version,
+        }
+    }
+
+    fn available_source(id: &str, version: usize) -> AvailableSource {
+        AvailableSource {
+            information: source_information(id, version),
+            aix_url: Url::parse(&format!("https://example.com/sources/{id}-v{version}.aix"))
+                .unwrap(),
+        }
+    }
+
+    #[test]
+    fn it_finds_sources_with_newer_versions() {
+        let installed_sources = vec![
+            source_information("outdated", 1),
+            source_information("up-to-date", 3),
+            source_information("not-listed", 1),
+        ];
+        let available_sources = vec![
+            available_source("outdated", 2),
+            available_source("outdated", 4),
+            available_source("up-to-date", 3),
+        ];
+
+        let updates = find_source_updates(installed_sources, &available_sources);
+
+        assert_eq!(updates.len(), 1);
+        assert_eq!(
+            updates[0].installed.id,
+            SourceId::new("outdated".to_string())
+        );
+        assert_eq!(updates[0].available.information.version, 4);
+        assert_eq!(
+            updates[0].available.aix_url.as_str(),
+            "https://example.com/sources/outdated-v4.aix"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use async_stream::stream;
+use futures::Stream;
+use log::info;
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+use crate::chapter_storage::{ChapterFileImport, ChapterStorage};
+use crate::model::SourceId;
+
+/// Moves all chapters stored in `previous_downloads_folder_path` into the chapter storage's
+/// current downloads folder, reporting the progress as each chapter is moved.
+///
+/// Files which don't look like chapters downloaded from one of `source_ids`, or which can't be
+/// moved into the storage, are left in place and reported when the migration finishes.
+pub fn migrate_chapter_storage(
+    cancellation_token: CancellationToken,
+    chapter_storage: ChapterStorage,
+    previous_downloads_folder_path: PathBuf,
+    source_ids: Vec<SourceId>,
+) -> impl Stream<Item = ProgressReport> {
+    stream! {
+        let files_to_import = match chapter_storage
+            .find_chapter_files_to_import(&previous_downloads_folder_path, &source_ids)
+        {
+            Ok(files_to_import) => files_to_import,
+            Err(e) => {
+                yield ProgressReport::Errored(Error::Other(e));
+
+                return;
+            }
+        };
+
+        let mut skipped: Vec<_> = files_to_import
+            .unrecognized
+            .into_iter()
+            .map(|path| SkippedChapterFile {
+                path,
+                reason: SkipReason::UnrecognizedName,
+            })
+            .collect();
+        let unrecognized = skipped.len();
+        let mut migrated = 0;
+
+        let total = files_to_import.chapter_files.len();
+        yield ProgressReport::Progressing { migrated, skipped: 0, total };
+
+        // Walking the whole storage is slow, so it's only done once, and the size is then kept
+        // up to date as chapters are imported.
+        let mut storage_size = {
+            let chapter_storage = chapter_storage.clone();
+
+            match tokio::task::spawn_blocking(move || chapter_storage.calculate_storage_size()).await {
+                Ok(storage_size) => storage_size,
+                Err(e) => {
+                    yield ProgressReport::Errored(Error::Other(e.into()));
+
+                    return;
+                }
+            }
+        };
+
+        for chapter_file in files_to_import.chapter_files {
+            let chapter_storage = chapter_storage.clone();
+            let path = chapter_file.clone();
+
+            // Moving files across devices might mean copying gigabytes around, so keep it out of
+            // the async runtime.
+            let import_result = select! {
+                _ = cancellation_token.cancelled() => {
+                    yield ProgressReport::Cancelled { migrated, skipped };
+
+                    return;
+                },
+                result = tokio::task::spawn_blocking(move || {
+                    chapter_storage
+                        .import_chapter_file(&path, storage_size)
+                        .with_context(|| format!("while migrating {}", path.display()))
+                }) => result
+            };
+
+            match import_result {
+                Ok(Ok(ChapterFileImport::Imported { path, size })) => {
+                    info!("migrated chapter to {}", path.display());
+
+                    migrated += 1;
+                    storage_size += size;
+                }
+                Ok(Ok(ChapterFileImport::AlreadyStored)) => skipped.push(SkippedChapterFile {
+                    path: chapter_file,
+                    reason: SkipReason::AlreadyStored,
+                }),
+                Ok(Ok(ChapterFileImport::StorageFull)) => skipped.push(SkippedChapterFile {
+                    path: chapter_file,
+                    reason: SkipReason::StorageFull,
+                }),
+                Ok(Err(e)) => {
+                    yield ProgressReport::Errored(Error::Other(e));
+
+                    return;
+                }
+                Err(e) => {
+                    yield ProgressReport::Errored(Error::Other(e.into()));
+
+                    return;
+                }
+            }
+
+            yield ProgressReport::Progressing {
+                migrated,
+                skipped: skipped.len() - unrecognized,
+                total,
+            };
+        }
+
+        yield ProgressReport::Finished { migrated, skipped };
+    }
+}
+
+pub enum ProgressReport {
+    Progressing {
+        migrated: usize,
+        skipped: usize,
+        total: usize,
+    },
+    Finished {
+        migrated: usize,
+        skipped: Vec<SkippedChapterFile>,
+    },
+    /// Chapters which were already moved stay in the new storage, so they're still reported.
+    Cancelled {
+        migrated: usize,
+        skipped: Vec<SkippedChapterFile>,
+    },
+    Errored(Error),
+}
+
+#[derive(Clone, Debug)]
+pub struct SkippedChapterFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SkipReason {
+    /// The file isn't named like the chapters we download.
+    UnrecognizedName,
+    /// A chapter with the same name is already in the storage.
+    AlreadyStored,
+    /// The chapter doesn't fit in the storage size limit.
+    StorageFull,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("couldn't migrate the chapter storage: {0:#}")]
+    Other(#[from] anyhow::Error),
+}
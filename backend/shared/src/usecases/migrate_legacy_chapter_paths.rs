@@ -0,0 +1,48 @@
+use anyhow::Result;
+use log::{info, warn};
+
+use crate::{
+    chapter_storage::{ChapterStorage, LegacyChaptersMigration},
+    database::Database,
+};
+
+/// Renames the chapters stored with the legacy `<source>-<chapter>.cbz` path format, using the
+/// chapter informations cached in the database to figure out which chapter each file belongs to.
+///
+/// If every legacy file could be migrated, the chapter storage stops looking for legacy paths.
+/// Files that could belong to more than one chapter are left untouched, as renaming them could
+/// make them show up as a chapter of the wrong manga.
+pub async fn migrate_legacy_chapter_paths(
+    db: &Database,
+    chapter_storage: &mut ChapterStorage,
+) -> Result<LegacyChaptersMigration> {
+    let chapter_ids = db.find_all_cached_chapter_ids().await;
+    let migration = chapter_storage.migrate_legacy_chapters(chapter_ids)?;
+
+    if migration.migrated > 0 {
+        info!(
+            "migrated {} chapters to the new path format",
+            migration.migrated
+        );
+    }
+
+    for path in &migration.unmapped {
+        warn!(
+            "couldn't find which chapter {} belongs to, leaving it with the legacy path format",
+            path.display()
+        );
+    }
+
+    for path in &migration.ambiguous {
+        warn!(
+            "{} matches chapters from more than one manga, leaving it with the legacy path format",
+            path.display()
+        );
+    }
+
+    if migration.unmapped.is_empty() && migration.ambiguous.is_empty() {
+        chapter_storage.disable_legacy_path_fallback();
+    }
+
+    Ok(migration)
+}
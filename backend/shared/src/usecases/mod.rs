@@ -1,17 +1,23 @@
 pub mod add_manga_to_library;
 pub mod check_update;
+pub mod clear_source_cookies;
 pub mod fetch_manga_chapter;
 pub mod fetch_manga_chapters_in_batch;
 pub mod get_cached_manga_chapters;
 pub mod get_manga_library;
 pub mod get_manga_preferred_scanlator;
+pub mod get_source_cookies;
 pub mod get_source_setting_definitions;
+pub mod get_source_store_metrics;
 pub mod get_source_stored_settings;
 pub mod install_source;
 pub mod install_update;
 pub mod list_available_sources;
 pub mod list_installed_sources;
+pub mod list_source_updates;
 pub mod mark_chapter_as_read;
+pub mod migrate_chapter_storage;
+pub mod migrate_legacy_chapter_paths;
 pub mod refresh_manga_chapters;
 pub mod remove_manga_from_library;
 pub mod search_mangas;
@@ -22,18 +28,24 @@ pub mod update_settings;
 
 pub use add_manga_to_library::add_manga_to_library;
 pub use check_update::check_update;
+pub use clear_source_cookies::clear_source_cookies;
 pub use fetch_manga_chapter::fetch_manga_chapter;
 pub use fetch_manga_chapters_in_batch::fetch_manga_chapters_in_batch;
 pub use get_cached_manga_chapters::get_cached_manga_chapters;
 pub use get_manga_library::get_manga_library;
 pub use get_manga_preferred_scanlator::get_manga_preferred_scanlator;
+pub use get_source_cookies::get_source_cookies;
 pub use get_source_setting_definitions::get_source_setting_definitions;
+pub use get_source_store_metrics::get_source_store_metrics;
 pub use get_source_stored_settings::get_source_stored_settings;
 pub use install_source::install_source;
 pub use install_update::install_update;
 pub use list_available_sources::list_available_sources;
 pub use list_installed_sources::list_installed_sources;
+pub use list_source_updates::list_source_updates;
 pub use mark_chapter_as_read::mark_chapter_as_read;
+pub use migrate_chapter_storage::migrate_chapter_storage;
+pub use migrate_legacy_chapter_paths::migrate_legacy_chapter_paths;
 pub use refresh_manga_chapters::refresh_manga_chapters;
 pub use remove_manga_from_library::remove_manga_from_library;
 pub use search_mangas::search_mangas;
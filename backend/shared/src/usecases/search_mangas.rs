@@ -7,13 +7,12 @@ use futures::{stream, StreamExt};
 use log::warn;
 use tokio_util::sync::CancellationToken;
 
-const CONCURRENT_SEARCH_REQUESTS: usize = 5;
-
 pub async fn search_mangas(
     source_collection: &impl SourceCollection,
     db: &Database,
     cancellation_token: CancellationToken,
     query: String,
+    concurrent_requests: usize,
 ) -> Result<Vec<Manga>, Error> {
     // FIXME this looks awful
     let query = &query;
@@ -73,7 +72,7 @@ pub async fn search_mangas(
                 mangas,
             }
         })
-        .buffered(CONCURRENT_SEARCH_REQUESTS)
+        .buffered(concurrent_requests)
         .collect::<Vec<_>>()
         .await;
 
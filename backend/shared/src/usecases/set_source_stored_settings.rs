@@ -23,7 +23,7 @@ pub fn set_source_stored_settings(
         .insert(source_id.value().clone(), stored_settings);
     updated_settings.save_to_file(settings_path)?;
 
-    source_manager.update_settings(updated_settings.clone())?;
+    source_manager.update_source_settings(source_id, updated_settings.clone());
     *settings = updated_settings;
 
     Ok(())
@@ -1,19 +1,90 @@
-use std::net::SocketAddr;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
+use futures::future::{select_ok, BoxFuture};
 use tokio::net::TcpStream;
 
-pub async fn has_internet_connection() -> bool {
-    try_connecting_to_cloudflare().await.is_ok()
+use crate::settings::{ConnectivityCheckSettings, NetworkSettings};
+
+const DEFAULT_TARGETS: [&str; 2] = ["1.0.0.1:80", "1.1.1.1:80"];
+const DEFAULT_CACHE_DURATION: Duration = Duration::from_secs(10);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks whether an internet connection is available, by trying to connect to any of the
+/// configured targets.
+///
+/// The result of a check is reused for a short while, so that we don't need to check again
+/// for every request.
+#[derive(Debug, Default)]
+pub struct ConnectivityChecker {
+    settings: ConnectivityCheckSettings,
+    proxy_address: Option<String>,
+    last_check: Mutex<Option<(Instant, bool)>>,
 }
 
-async fn try_connecting_to_cloudflare() -> Result<()> {
-    let addrs = [
-        SocketAddr::from(([1, 0, 0, 1], 80)),
-        SocketAddr::from(([1, 1, 1, 1], 80)),
-    ];
+impl ConnectivityChecker {
+    pub fn new(settings: &NetworkSettings) -> Self {
+        // When using a proxy, being able to reach it is what matters: the default targets might
+        // not even be reachable without going through it.
+        let proxy_address = settings.proxy.as_ref().and_then(|proxy| {
+            Some(format!(
+                "{}:{}",
+                proxy.host_str()?,
+                proxy.port_or_known_default()?
+            ))
+        });
+
+        Self {
+            settings: settings.connectivity_check.clone(),
+            proxy_address,
+            last_check: Mutex::new(None),
+        }
+    }
+
+    pub async fn has_internet_connection(&self) -> bool {
+        if self.settings.disabled {
+            return true;
+        }
+
+        let cache_duration = self
+            .settings
+            .cache_seconds
+            .map_or(DEFAULT_CACHE_DURATION, Duration::from_secs);
+
+        if let Some((checked_at, result)) = *self.last_check.lock().unwrap() {
+            if checked_at.elapsed() < cache_duration {
+                return result;
+            }
+        }
+
+        let result = self.probe().await.is_ok();
+        *self.last_check.lock().unwrap() = Some((Instant::now(), result));
+
+        result
+    }
+
+    async fn probe(&self) -> Result<()> {
+        let targets: Vec<&str> = if !self.settings.targets.is_empty() {
+            self.settings.targets.iter().map(String::as_str).collect()
+        } else if let Some(proxy_address) = &self.proxy_address {
+            vec![proxy_address.as_str()]
+        } else {
+            DEFAULT_TARGETS.to_vec()
+        };
+
+        let attempts = targets.into_iter().map(|target| -> BoxFuture<Result<()>> {
+            Box::pin(async move {
+                tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(target)).await??;
+
+                Ok(())
+            })
+        });
 
-    TcpStream::connect(&addrs[..]).await?;
+        select_ok(attempts).await?;
 
-    Ok(())
+        Ok(())
+    }
 }
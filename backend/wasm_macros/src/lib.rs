@@ -91,21 +91,28 @@ pub fn aidoku_wasm_function(_args: OGTokenStream, input: OGTokenStream) -> OGTok
 
         pub fn #internal_ident(mut caller: ::wasmi::Caller<'_, #caller_store_type>, params: &[::wasmi::Val], results: &mut [::wasmi::Val]) -> ::core::result::Result<(), ::wasmi::Error> {
             use ::wasm_shared::WasmFunctionReturnType;
-            #argument_accessor_start
-            #(#argument_setters)*
 
-            let result = #ident(caller, #(#function_call_parameters, )*);
+            // A panic must not unwind through the WASM runtime (and into whoever holds the
+            // source), so we turn it into a trap instead.
+            let source_id = caller.data().id.clone();
+            ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(move || {
+                #argument_accessor_start
+                #(#argument_setters)*
 
-            result.write_return_values(stringify!(#ident), results);
+                let result = #ident(caller, #(#function_call_parameters, )*);
 
-            Ok(())
+                result.write_return_values(stringify!(#ident), results);
+            }))
+            .map_err(|payload| {
+                ::wasmi::Error::host(::wasm_shared::ImportError::from_panic(source_id, stringify!(#ident), payload))
+            })
         }
 
-        pub fn #register_wasm_function_ident<'a>(
-            linker: &'a mut ::wasmi::Linker<#caller_store_type>,
+        pub fn #register_wasm_function_ident<'a, L: ::wasm_shared::HostFunctionLinker<#caller_store_type>>(
+            linker: &'a mut L,
             module: &str,
             name: &str
-        ) -> ::core::result::Result<&'a mut ::wasmi::Linker<#caller_store_type>, ::wasmi::errors::LinkerError> {
+        ) -> ::core::result::Result<&'a mut L, ::wasmi::errors::LinkerError> {
             use ::wasm_shared::ToWasmValue;
 
             #wasm_parameter_types_array_definition
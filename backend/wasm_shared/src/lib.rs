@@ -1,9 +1,12 @@
+use std::{any::Any, fmt};
+
 use anyhow::{anyhow, Ok, Result};
 use chrono::DateTime;
 use memory_reader::{read_bytes, read_string};
 use wasmi::{
     core::{ValType, F64},
-    Caller, Extern, Memory, Val,
+    errors::LinkerError,
+    Caller, Extern, FuncType, Linker, Memory, Val,
 };
 
 pub mod memory_reader;
@@ -15,6 +18,80 @@ pub fn get_memory<T>(caller: &mut Caller<'_, T>) -> Option<Memory> {
     }
 }
 
+/// Somewhere host functions can be defined, so that WASM modules can import them.
+pub trait HostFunctionLinker<T> {
+    fn func_new(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        func: impl Fn(Caller<'_, T>, &[Val], &mut [Val]) -> core::result::Result<(), wasmi::Error>
+            + Send
+            + Sync
+            + 'static,
+    ) -> core::result::Result<&mut Self, LinkerError>;
+}
+
+impl<T> HostFunctionLinker<T> for Linker<T> {
+    fn func_new(
+        &mut self,
+        module: &str,
+        name: &str,
+        ty: FuncType,
+        func: impl Fn(Caller<'_, T>, &[Val], &mut [Val]) -> core::result::Result<(), wasmi::Error>
+            + Send
+            + Sync
+            + 'static,
+    ) -> core::result::Result<&mut Self, LinkerError> {
+        Linker::func_new(self, module, name, ty, func)
+    }
+}
+
+/// A failure inside an imported function that can't be reported back to the source through
+/// its return value (i.e. a panic), which aborts the execution of the source with a trap.
+#[derive(Debug)]
+pub struct ImportError {
+    pub source_id: String,
+    pub import: &'static str,
+    pub message: String,
+}
+
+impl ImportError {
+    pub fn from_panic(
+        source_id: String,
+        import: &'static str,
+        payload: Box<dyn Any + Send>,
+    ) -> Self {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_string()
+        };
+
+        Self {
+            source_id,
+            import,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "import `{}` failed in source `{}`: {}",
+            self.import, self.source_id, self.message
+        )
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl wasmi::core::HostError for ImportError {}
+
 pub trait FromWasmValues<T> {
     const WASM_VALUE_COUNT: usize;
 
@@ -150,6 +150,10 @@ end
 --- @field name string The name of the source.
 --- @field version number The version of the source.
 
+--- @class InstalledSourceInformation: SourceInformation
+--- @field failed_to_load boolean Whether the source couldn't be loaded, in which case it can only be reinstalled or removed.
+--- @field load_error string|nil Why the source couldn't be loaded.
+
 --- @class Manga
 --- @field id string The ID of the manga.
 --- @field source SourceInformation The source information for this manga.
@@ -283,7 +287,7 @@ function Backend.markChapterAsRead(source_id, manga_id, chapter_id)
 end
 
 --- Lists information about the installed sources.
---- @return SuccessfulResponse<SourceInformation[]>|ErrorResponse
+--- @return SuccessfulResponse<InstalledSourceInformation[]>|ErrorResponse
 function Backend.listInstalledSources()
   return Backend.requestJson({
     path = "/installed-sources",
@@ -436,6 +440,21 @@ function Backend.createDownloadScanlatorChaptersJob(source_id, manga_id, scanlat
   })
 end
 
+--- @class LegacyChaptersMigrationReport
+--- @field migrated number
+--- @field unmapped string[] Chapter files whose chapter couldn't be found.
+--- @field ambiguous string[] Chapter files which could belong to chapters of more than one manga.
+
+--- Creates a new job to move the chapters from the previous storage path into the current one.
+--- Returns the job's UUID.
+--- @return SuccessfulResponse<string>|ErrorResponse
+function Backend.createMigrateChapterStorageJob()
+  return Backend.requestJson({
+    path = "/jobs/migrate-chapter-storage",
+    method = 'POST',
+  })
+end
+
 --- @class PendingJob<T>: { type: 'PENDING', data: T }
 --- @class CompletedJob<T>: { type: 'COMPLETED', data: T }
 --- @class ErroredJob: { type: 'ERROR', data: ErrorResponse }
@@ -11,7 +11,7 @@ local SourceSettings = require("SourceSettings")
 local Testing = require("testing")
 
 --- @class InstalledSourcesListing: { [any]: any }
---- @field installed_sources SourceInformation[]
+--- @field installed_sources InstalledSourceInformation[]
 --- @field on_return_callback fun(): nil
 local InstalledSourcesListing = Menu:extend {
   name = "installed_sources_listing",
@@ -63,14 +63,19 @@ end
 
 --- Generates the item table for displaying the search results.
 --- @private
---- @param installed_sources SourceInformation[]
+--- @param installed_sources InstalledSourceInformation[]
 --- @return table
 function InstalledSourcesListing:generateItemTableFromInstalledSources(installed_sources)
   local item_table = {}
   for _, source_information in ipairs(installed_sources) do
+    local text = source_information.name .. " (version " .. source_information.version .. ")"
+    if source_information.failed_to_load then
+      text = source_information.name .. " (failed to load)"
+    end
+
     table.insert(item_table, {
       source_information = source_information,
-      text = source_information.name .. " (version " .. source_information.version .. ")",
+      text = text,
     })
   end
 
@@ -92,9 +97,18 @@ end
 
 --- @private
 function InstalledSourcesListing:onPrimaryMenuChoice(item)
-  --- @type SourceInformation
+  --- @type InstalledSourceInformation
   local source_information = item.source_information
 
+  if source_information.failed_to_load then
+    ErrorDialog:show(
+      "This source couldn't be loaded: " .. (source_information.load_error or "unknown error") ..
+      "\n\nReinstall it from the available sources, or hold it to remove it."
+    )
+
+    return
+  end
+
   local on_return_callback = function()
     self:fetchAndShow(self.on_return_callback)
   end
@@ -106,7 +120,7 @@ end
 
 --- @private
 function InstalledSourcesListing:onContextMenuChoice(item)
-  --- @type SourceInformation
+  --- @type InstalledSourceInformation
   local source_information = item.source_information
 
   UIManager:show(ConfirmBox:new {
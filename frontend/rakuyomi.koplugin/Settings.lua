@@ -1,13 +1,16 @@
 local Blitbuffer = require("ffi/blitbuffer")
+local ConfirmBox = require("ui/widget/confirmbox")
 local FocusManager = require("ui/widget/focusmanager")
 local FrameContainer = require("ui/widget/container/framecontainer")
 local Geom = require("ui/geometry")
 local HorizontalGroup = require("ui/widget/horizontalgroup")
 local HorizontalSpan = require("ui/widget/horizontalspan")
+local InfoMessage = require("ui/widget/infomessage")
 local OverlapGroup = require("ui/widget/overlapgroup")
 local Screen = require("device").screen
 local Size = require("ui/size")
 local TitleBar = require("ui/widget/titlebar")
+local Trapper = require("ui/trapper")
 local UIManager = require("ui/uimanager")
 local VerticalGroup = require("ui/widget/verticalgroup")
 local logger = require("logger")
@@ -15,6 +18,8 @@ local Paths = require("Paths")
 
 local Backend = require("Backend")
 local ErrorDialog = require("ErrorDialog")
+local LoadingDialog = require("LoadingDialog")
+local MigrateChapterStorage = require("jobs/MigrateChapterStorage")
 local SettingItem = require('widgets/SettingItem')
 
 -- REFACT This is duplicated from `SourceSettings` (pretty much all of it actually)
@@ -169,7 +174,70 @@ function Settings:updateSetting(key, value)
   local response = Backend.setSettings(self.settings)
   if response.type == 'ERROR' then
     ErrorDialog:show(response.message)
+
+    return
+  end
+
+  if key == 'storage_path' then
+    self:offerChapterStorageMigration()
+  end
+end
+
+--- @private
+function Settings:offerChapterStorageMigration()
+  UIManager:show(ConfirmBox:new {
+    text = "Do you want to move the chapters downloaded to the previous storage path into the new one?",
+    ok_text = "Move",
+    ok_callback = function()
+      Trapper:wrap(function()
+        self:migrateChapterStorage()
+      end)
+    end
+  })
+end
+
+--- @private
+function Settings:migrateChapterStorage()
+  local job = MigrateChapterStorage:new()
+  if job == nil then
+    ErrorDialog:show('Could not start moving the downloaded chapters.')
+
+    return
   end
+
+  local response = LoadingDialog:showAndRun(
+    "Moving downloaded chapters…",
+    function()
+      return job:runUntilCompletion()
+    end
+  )
+
+  if response.type == 'ERROR' then
+    ErrorDialog:show(response.message)
+
+    return
+  end
+
+  local message = 'Moved ' .. response.body.migrated .. ' chapters.'
+  if #response.body.skipped > 0 then
+    message = message .. ' ' .. #response.body.skipped ..
+        ' files were left in the previous storage path, as they were already present in the new one, ' ..
+        'did not fit in the storage size limit or were not downloaded by rakuyomi.'
+  end
+
+  local legacy_chapters = response.body.legacy_chapters
+  if legacy_chapters ~= nil then
+    local unmigrated = #legacy_chapters.unmapped + #legacy_chapters.ambiguous
+    if unmigrated > 0 then
+      message = message .. ' ' .. unmigrated ..
+          ' chapters downloaded by an older version of rakuyomi could not be matched to a single chapter, ' ..
+          'and might not show up as downloaded.'
+    end
+  end
+
+  UIManager:show(InfoMessage:new {
+    text = message,
+  })
 end
 
 function Settings:fetchAndShow(on_return_callback)
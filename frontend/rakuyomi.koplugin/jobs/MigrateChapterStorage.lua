@@ -0,0 +1,56 @@
+local logger = require('logger')
+
+local Backend = require('Backend')
+local Job = require('jobs/Job')
+
+--- @class SkippedChapterFile
+--- @field path string
+--- @field reason 'UNRECOGNIZED_NAME'|'ALREADY_STORED'|'STORAGE_FULL'
+
+--- @class MigrateChapterStorageOutput
+--- @field migrated number
+--- @field skipped SkippedChapterFile[]
+--- @field legacy_chapters LegacyChaptersMigrationReport|nil Missing if the legacy chapters couldn't be migrated.
+
+--- @class MigrateChapterStorage: Job
+--- @field private job_id string
+local MigrateChapterStorage = Job:extend()
+
+--- Creates a new `MigrateChapterStorage` job.
+---
+--- @return self|nil job A new `MigrateChapterStorage` job, or `nil`, if the job could not be created.
+function MigrateChapterStorage:new()
+  local o = {}
+  setmetatable(o, self)
+  self.__index = self
+
+  if not o:start() then
+    return nil
+  end
+
+  return o
+end
+
+--- Starts the job. Should be called automatically when instantiating a job with `new()`.
+---
+--- @private
+--- @return boolean success Whether the job started successfully.
+function MigrateChapterStorage:start()
+  local response = Backend.createMigrateChapterStorageJob()
+  if response.type == 'ERROR' then
+    logger.error('could not create migrate chapter storage job', response.message)
+
+    return false
+  end
+
+  self.job_id = response.body
+
+  return true
+end
+
+--- @return SuccessfulResponse<MigrateChapterStorageOutput>|ErrorResponse
+function MigrateChapterStorage:runUntilCompletion()
+  return Job.runUntilCompletion(self)
+end
+
+return MigrateChapterStorage